@@ -6,37 +6,253 @@ use std::io;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::io::BufRead;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use libjlox::{Diagnostic, Interpreter, Phase, RunOptions, Severity, Timings};
+
+// Exit codes from sysexits.h, as used by the reference implementation.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
 
 
 fn main() {
+    libjlox::with_large_stack(cli)
+}
+
+fn cli() {
     let matches = App::new("rlox interpreter")
-    .version(".01").author("Ian Smith").arg(Arg::with_name("SCRIPT").help("The script to run").required(false).index(1)).get_matches();
+    .version(".01").author("Ian Smith").arg(Arg::with_name("SCRIPT").help("The script to run").required(false).index(1))
+    .arg(Arg::with_name("time").long("time").help("Reports how long each phase took"))
+    .arg(Arg::with_name("no-color").long("no-color").help("Prints errors without color"))
+    .arg(Arg::with_name("tokens").long("tokens").alias("dump-tokens").help("Prints the scanned tokens instead of running"))
+    .arg(Arg::with_name("dump-ast").long("dump-ast").help("Prints the parsed syntax tree instead of running"))
+    .arg(Arg::with_name("check").long("check").help("Reports problems found without running, reading stdin when no script is given"))
+    .arg(Arg::with_name("optimize").long("optimize").help("Folds constant expressions before running"))
+    .arg(Arg::with_name("strict").long("strict").help("Makes + take two numbers or two strings"))
+    .arg(Arg::with_name("finite").long("finite").help("Makes arithmetic yielding an infinity or NaN fail"))
+    .arg(Arg::with_name("max-depth").long("max-depth").takes_value(true).value_name("N").help("Limits how deeply calls may nest"));
+    #[cfg(feature = "serde")]
+    let matches = matches.arg(Arg::with_name("emit-json").long("emit-json").help("Prints the parsed syntax tree as JSON instead of running"));
+    #[cfg(feature = "serde")]
+    let matches = matches.arg(Arg::with_name("json").long("json").help("Prints --tokens and --dump-ast output as JSON"));
+    let matches = matches.get_matches();
     let script = matches.value_of("SCRIPT");
+    let time = matches.is_present("time");
+    if matches.is_present("no-color") {
+    	libjlox::set_color_output(false);
+    }
+    let optimize = matches.is_present("optimize");
+    let max_depth = match matches.value_of("max-depth").map(str::parse) {
+    	None => RunOptions::default().max_depth,
+    	Some(Ok(depth)) => depth,
+    	Some(Err(_)) => {
+    		eprintln!("--max-depth expects a whole number");
+    		process::exit(EX_USAGE);
+    	}
+    };
+    let strict_plus = matches.is_present("strict");
+    let finite_arithmetic = matches.is_present("finite");
+    let options = RunOptions { optimize, max_depth, strict_plus, finite_arithmetic, ..RunOptions::default() };
 
-    match script {
-    	None => run_prompt().unwrap(),
-    	Some(file) => run_file(file).unwrap()
+    let mut dumper: Option<Dumper> = None;
+    if matches.is_present("tokens") {
+    	dumper = Some(tokens);
+    }
+    if matches.is_present("dump-ast") {
+    	dumper = Some(if optimize { libjlox::dump_optimized_ast } else { libjlox::dump_ast });
+    }
+    #[cfg(feature = "serde")]
+    if matches.is_present("json") {
+    	if matches.is_present("tokens") {
+    		dumper = Some(tokens_json);
+    	}
+    	if matches.is_present("dump-ast") {
+    		dumper = Some(if optimize { libjlox::parse_to_optimized_json } else { libjlox::parse_to_json });
+    	}
+    }
+    #[cfg(feature = "serde")]
+    if matches.is_present("emit-json") {
+    	dumper = Some(libjlox::parse_to_json);
+    }
+
+    if matches.is_present("check") {
+    	match check(script) {
+    		Ok(code) => process::exit(code),
+    		Err(err) => {
+    			eprintln!("Could not read {}: {}", script.unwrap_or("input"), err);
+    			process::exit(EX_NOINPUT);
+    		}
+    	}
+    }
+
+    let res = match (script, dumper) {
+    	(None, Some(dumper)) => dump_prompt(dumper).map(|_| 0),
+    	(Some(file), Some(dumper)) => dump_file(file, dumper),
+    	(None, None) => run_prompt(time, options).map(|_| 0),
+    	(Some(file), None) => run_file(file, time, options)
+    };
+
+    match res {
+    	Ok(code) => process::exit(code),
+    	Err(err) => {
+    		eprintln!("Could not read {}: {}", script.unwrap_or("input"), err);
+    		process::exit(EX_NOINPUT);
+    	}
     }
 }
 
 
-fn run_prompt() -> io::Result<()> {
+// One interpreter lasts the whole session, so each entry sees the globals
+// the ones before it defined.
+fn run_prompt(time: bool, options: RunOptions) -> io::Result<()> {
 	let mut rdr = BufReader::new(io::stdin());
-	
-	loop {	
-		print!("> ");
-		io::stdout().flush().unwrap();
-		let mut contents = String::new();
-		rdr.read_line(&mut contents)?;
-	    libjlox::run(contents)
+	let mut contents = String::new();
+	let mut interpreter = Interpreter::new();
+	options.configure(&mut interpreter);
+	let options = RunOptions { echo: true, ..options };
+
+	loop {
+		print!("{}", if contents.is_empty() { "> " } else { "... " });
+		io::stdout().flush()?;
+
+		// Ctrl-D cancels a partial statement and otherwise ends the session,
+		// a blank line submits a partial statement as is.
+		let line = match read_prompt_line(&mut rdr) {
+			Some(line) => line,
+			None if contents.is_empty() => break,
+			None => {
+				contents.clear();
+				continue;
+			}
+		};
+
+		let submit = !contents.is_empty() && line.trim().is_empty();
+		contents.push_str(&line);
+		if !submit && libjlox::is_incomplete(&contents) {
+			continue;
+		}
+
+		let (diagnostics, timings) = libjlox::run_in_with_options(std::mem::take(&mut contents), &mut interpreter, options.clone());
+		report(diagnostics, timings, time);
+		io::stdout().flush()?;
+	}
+
+	Ok(())
+}
+
+// Returns None at end of input. A line that can't be read is reported and
+// skipped so it doesn't end the session.
+fn read_prompt_line(rdr: &mut impl BufRead) -> Option<String> {
+	loop {
+		let mut line = String::new();
+		match rdr.read_line(&mut line) {
+			Ok(0) => {
+				println!();
+				return None;
+			},
+			Ok(_) => return Some(line),
+			Err(err) => eprintln!("Could not read input: {}", err),
+		}
 	}
 }
 
-fn run_file(fname: &str) -> io::Result<()> {
+fn run_file(fname: &str, time: bool, options: RunOptions) -> io::Result<i32> {
 	let mut file = File::open(fname)?;
 	let mut contents = String::new();
 	file.read_to_string(&mut contents)?;
-	libjlox::run(contents);
-	Ok(())
+	Ok(run(contents, time, RunOptions { script_path: Some(PathBuf::from(fname)), ..options }))
+}
+
+fn check(fname: Option<&str>) -> io::Result<i32> {
+	let mut contents = String::new();
+	match fname {
+		Some(fname) => File::open(fname)?.read_to_string(&mut contents)?,
+		None => io::stdin().read_to_string(&mut contents)?
+	};
+	Ok(print_diagnostics(libjlox::check_only(contents)))
+}
+
+type Dumper = fn(String) -> Result<String, Vec<Diagnostic>>;
+
+fn dump_prompt(dumper: Dumper) -> io::Result<()> {
+	let mut rdr = BufReader::new(io::stdin());
+
+	loop {
+		print!("> ");
+		io::stdout().flush()?;
+		match read_prompt_line(&mut rdr) {
+			Some(contents) => {
+				dump(contents, dumper);
+			},
+			None => return Ok(())
+		}
+	}
+}
+
+fn dump_file(fname: &str, dumper: Dumper) -> io::Result<i32> {
+	let mut contents = String::new();
+	File::open(fname)?.read_to_string(&mut contents)?;
+	Ok(dump(contents, dumper))
+}
+
+fn dump(contents: String, dumper: Dumper) -> i32 {
+	match dumper(contents) {
+		Ok(tree) => {
+			println!("{}", tree.trim_end());
+			0
+		},
+		Err(diagnostics) => print_diagnostics(diagnostics)
+	}
+}
+
+fn tokens(contents: String) -> Result<String, Vec<Diagnostic>> {
+	let (tokens, diagnostics) = libjlox::dump_tokens(contents);
+	print_diagnostics(diagnostics);
+	Ok(tokens)
+}
+
+#[cfg(feature = "serde")]
+fn tokens_json(contents: String) -> Result<String, Vec<Diagnostic>> {
+	let (tokens, diagnostics) = libjlox::dump_tokens_json(contents);
+	print_diagnostics(diagnostics);
+	Ok(tokens)
+}
+
+fn run(contents: String, time: bool, options: RunOptions) -> i32 {
+	let (diagnostics, timings) = libjlox::run_with_options(contents, options);
+	report(diagnostics, timings, time)
+}
+
+fn report(diagnostics: Vec<Diagnostic>, timings: Timings, time: bool) -> i32 {
+	let code = print_diagnostics(diagnostics);
+	if time {
+		eprintln!("scan: {}, parse: {}, check: {}, interpret: {}", millis(timings.scan), millis(timings.parse),
+			millis(timings.check), millis(timings.interpret));
+	}
+	code
+}
+
+fn millis(duration: Duration) -> String {
+	format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+// Prints the diagnostics and picks the exit code they call for.
+fn print_diagnostics(diagnostics: Vec<Diagnostic>) -> i32 {
+	for diagnostic in diagnostics.iter() {
+		diagnostic.print_to_stderr();
+	}
+
+	let errors = || diagnostics.iter().filter(|d| d.severity == Severity::Error);
+	if errors().any(|d| d.phase == Phase::Runtime) {
+		EX_SOFTWARE
+	} else if errors().next().is_some() {
+		EX_DATAERR
+	} else {
+		0
+	}
 }
 
@@ -0,0 +1,86 @@
+use crate::functions::Callable;
+use crate::functions::LoxFunction;
+use crate::interpreter::InterpreterError;
+use crate::interpreter::Result;
+use crate::interpreter::RuntimeError;
+use crate::tokens::Literal;
+use crate::tokens::Token;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub struct LoxClass {
+	name: String,
+	superclass: Option<Rc<LoxClass>>,
+	methods: HashMap<String, LoxFunction>
+}
+
+impl LoxClass {
+	pub fn new(name: String, superclass: Option<Rc<LoxClass>>, methods: HashMap<String, LoxFunction>) -> LoxClass {
+		LoxClass {
+			name,
+			superclass,
+			methods
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	// Looks through the superclass chain when the class itself doesn't
+	// define the method.
+	pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
+		match self.methods.get(name) {
+			Some(method) => Some(method),
+			None => self.superclass.as_ref().and_then(|class| class.find_method(name)),
+		}
+	}
+}
+
+pub struct Instance {
+	class: Rc<LoxClass>,
+	fields: HashMap<String, Literal>
+}
+
+impl Instance {
+	pub fn new(class: Rc<LoxClass>) -> Instance {
+		Instance {
+			class,
+			fields: HashMap::new()
+		}
+	}
+
+	pub fn class_name(&self) -> &str {
+		self.class.name()
+	}
+
+	// Fields shadow methods. Methods come back bound to the instance so they
+	// can be stored and called later.
+	pub fn get(instance: &Rc<RefCell<Instance>>, name: &Token) -> Result<Literal> {
+		let this = instance.borrow();
+
+		if let Some(value) = this.fields.get(name.get_lexeme()) {
+			return Ok(value.clone());
+		}
+
+		match this.class.find_method(name.get_lexeme()) {
+			Some(method) => {
+				let method = method.bind(Literal::Instance(instance.clone()));
+				Ok(Literal::Callable(Rc::new(Callable::Function(method))))
+			},
+			None => Err(RuntimeError::InterpreterError(InterpreterError::new(name, "Undefined property"))),
+		}
+	}
+
+	pub fn set(&mut self, name: &Token, value: Literal) {
+		self.fields.insert(name.get_lexeme().to_owned(), value);
+	}
+}
+
+impl std::fmt::Debug for Instance {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "<{} instance>", self.class_name())
+	}
+}
@@ -1,57 +1,161 @@
-use crate::ErrorReporter;
+use crate::Reporter;
 use crate::syntax::Stmt;
+use crate::syntax::StmtKind;
 use crate::tokens::Token;
 use crate::syntax::StmtVisitor;
+use crate::syntax::ExprVisitor;
+use crate::tokens::Literal;
+use crate::symbols::Slot;
 
 use crate::syntax::Expr;
+use crate::syntax::FunctionDecl;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
 
 
 type Result<T> = std::result::Result<T,ContextError>;
 
+// Every name a local scope binds, in the order the interpreter binds them,
+// and the names declared so far with whether each may have a value yet.
+struct Scope {
+    slots: Vec<String>,
+    declared: RefCell<HashMap<String, bool>>
+}
+
 pub enum ContextError {
-    BreakOutsideLoop(usize)
+    BreakOutsideLoop(usize),
+    ContinueOutsideLoop(usize),
+    UndefinedLabel(usize, String),
+    AssignToUndeclared(usize, String),
+    DuplicateDeclaration(usize, String),
+    ReadBeforeInit(usize, String),
+    EmptyStatement(usize),
+    ReturnOutsideFunction(usize),
+    ReturnValueFromInitializer(usize),
+    SuperOutsideSubclass(usize)
 }
 
 impl ContextError {
-    pub fn report(&self, err_rep: &mut ErrorReporter) {
+    pub fn report(&self, err_rep: &mut dyn Reporter) {
         match self {
-            ContextError::BreakOutsideLoop(line) => err_rep.error(*line, "Break found outside of loop body.")
+            ContextError::BreakOutsideLoop(line) => err_rep.error(*line, "Break found outside of loop body."),
+            ContextError::ContinueOutsideLoop(line) => err_rep.error(*line, "Continue found outside of loop body."),
+            ContextError::UndefinedLabel(line, label) => err_rep.error(*line, &format!("No enclosing loop is labelled '{}'.", label)),
+            ContextError::AssignToUndeclared(line, name) => err_rep.error(*line, &format!("Cannot assign to undeclared variable '{}'.", name)),
+            ContextError::DuplicateDeclaration(line, name) => err_rep.error(*line, &format!("Variable '{}' is already declared in this scope.", name)),
+            ContextError::ReadBeforeInit(line, name) => err_rep.error(*line, &format!("Variable '{}' used before initialization.", name)),
+            ContextError::EmptyStatement(line) => err_rep.warning(*line, "Empty statement."),
+            ContextError::ReturnOutsideFunction(line) => err_rep.error(*line, "Cannot return from top-level code."),
+            ContextError::ReturnValueFromInitializer(line) => err_rep.error(*line, "Cannot return a value from an initializer."),
+            ContextError::SuperOutsideSubclass(line) => err_rep.error(*line, "Cannot use 'super' outside of a subclass.")
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Initializer
+}
+
 #[derive(Clone)]
 struct ContextCheck {
-    inside_loop: bool
+    // Switches are break targets too, but only loops can be continued.
+    inside_loop: bool,
+    continue_target: bool,
+    labels: Vec<String>,
+    function: FunctionKind,
+    inside_subclass: bool,
+    warn_empty: bool,
+    // The names declared in each enclosing local scope, innermost last.
+    // Statements in a block share its scope, so the maps are shared too.
+    scopes: Vec<Rc<Scope>>,
+    // Index of the first scope belonging to the function being checked.
+    function_scope: usize,
+    // Every name that may be global. None when that can't be known, which
+    // leaves assignments to be checked as they run.
+    globals: Option<Rc<HashSet<String>>>
 }
 
 impl StmtVisitor<Result<()>> for ContextCheck {
-    fn visit_print(self, _expr: &Expr) -> Result<()> {
-        Ok(())
-    } 
+    fn visit_print(self, expr: &Expr) -> Result<()> {
+        expr.accept(self)
+    }
 
-    fn visit_break(self, line: usize) -> Result<()> {
-        if self.get_inside_loop() {
+    fn visit_break(self, line: usize, label: &Option<Token>) -> Result<()> {
+        if let Some(label) = label {
+            self.check_label(line, label)
+        } else if self.get_inside_loop() {
             Ok(())
         } else {
             Err(ContextError::BreakOutsideLoop(line))
         }
     }
 
-    fn visit_expr_statement(self, _expr: &Expr) -> Result<()> {
-        Ok(())
+    fn visit_continue(self, line: usize, label: &Option<Token>) -> Result<()> {
+        if let Some(label) = label {
+            self.check_label(line, label)
+        } else if self.continue_target {
+            Ok(())
+        } else {
+            Err(ContextError::ContinueOutsideLoop(line))
+        }
     }
 
-    fn visit_variable(self,_name: &Token, _expr: &Option<Expr>) -> Result<()> {
-        Ok(())
+    fn visit_empty(self, line: usize) -> Result<()> {
+        if self.warn_empty {
+            Err(ContextError::EmptyStatement(line))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_expr_statement(self, expr: &Expr) -> Result<()> {
+        expr.accept(self)
+    }
+
+    fn visit_variable(self, name: &Token, expr: &Option<Expr>) -> Result<()> {
+        self.declare(name, expr.is_some())?;
+        self.check_all(expr.iter())
+    }
+
+    fn visit_global(self,_name: &Token, expr: &Option<Expr>) -> Result<()> {
+        self.check_all(expr.iter())
     }
 
-    fn visit_while(mut self, _cond: &Expr, body: &Stmt) -> Result<()> {
+    fn visit_while(self, label: &Option<Token>, cond: &Expr, body: &Stmt, increment: &Option<Box<Expr>>) -> Result<()> {
+        self.clone().check_all(std::iter::once(cond).chain(increment.iter().map(|xp| &**xp)))?;
+        body.accept(self.enter_loop(label))
+    }
+
+    fn visit_do_while(self, label: &Option<Token>, body: &Stmt, cond: &Expr) -> Result<()> {
+        cond.accept(self.clone())?;
+        body.accept(self.enter_loop(label))
+    }
+
+    // A break inside a case leaves the switch, so cases are break targets too.
+    fn visit_switch(mut self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) -> Result<()> {
+        subject.accept(self.clone())?;
+        self.clone().check_all(cases.iter().map(|(value, _)| value))?;
         self.inside_loop = true;
-        body.accept(self)
+
+        for (_, stmts) in cases.iter() {
+            self.clone().visit_block_stmt(stmts)?;
+        }
+
+        if let Some(stmts) = default {
+            self.visit_block_stmt(stmts)
+        } else {
+            Ok(())
+        }
     }
 
-    fn visit_if(self, _cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) -> Result<()> {
+    fn visit_if(self, cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) -> Result<()> {
+        cond.accept(self.clone())?;
         then.accept(self.clone())?;
         if let Some(other) = otherwise {
             other.accept(self)
@@ -60,36 +164,494 @@ impl StmtVisitor<Result<()>> for ContextCheck {
         }
     }
 
-    fn visit_block_stmt(self, stmts: &Vec<Stmt>) -> Result<()> {
-        for stmt in stmts.iter() {
-            stmt.accept(self.clone())?;
+    fn visit_block_stmt(self, stmts: &[Stmt]) -> Result<()> {
+        self.enter_scope(scope_names(stmts)).check_stmts(stmts)
+    }
+
+    // The name was declared when the enclosing statements were hoisted.
+    fn visit_function(self, decl: &Rc<FunctionDecl>) -> Result<()> {
+        self.check_function(decl, FunctionKind::Function)
+    }
+
+    fn visit_return(self, keyword: &Token, value: &Option<Expr>) -> Result<()> {
+        match self.function {
+            FunctionKind::None => Err(ContextError::ReturnOutsideFunction(keyword.get_line())),
+            FunctionKind::Initializer if value.is_some() => Err(ContextError::ReturnValueFromInitializer(keyword.get_line())),
+            _ => self.check_all(value.iter())
+        }
+    }
+
+    // Methods close over a scope binding `super` in a subclass, and are
+    // bound to their instance in a scope of their own holding `this`.
+    fn visit_class(mut self, name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) -> Result<()> {
+        self.declare(name, true)?;
+        self.clone().check_all(superclass.iter())?;
+        self.inside_subclass = superclass.is_some();
+        if self.inside_subclass {
+            self = self.enter_scope(vec!["super".to_owned()]);
+            self.declare_name("super");
+        }
+
+        for method in methods.iter() {
+            let kind = if method.name.get_lexeme() == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Function
+            };
+            let checker = self.clone().enter_scope(vec!["this".to_owned()]);
+            checker.declare_name("this");
+            checker.check_function(method, kind)?;
         }
 
         Ok(())
     }
 
+    fn visit_import(self, _keyword: &Token, _path: &str) -> Result<()> {
+        Ok(())
+    }
+
+}
+
+impl ExprVisitor<Result<()>> for ContextCheck {
+    fn visit_binary(self, left: &Expr, _op: &Token, right: &Expr) -> Result<()> {
+        left.accept(self.clone())?;
+        right.accept(self)
+    }
+
+    fn visit_grouping(self, exp: &Expr) -> Result<()> {
+        exp.accept(self)
+    }
+
+    fn visit_literal(self, _lit: &Literal) -> Result<()> {
+        Ok(())
+    }
+
+    fn visit_unary(self, _op: &Token, exp: &Expr) -> Result<()> {
+        exp.accept(self)
+    }
+
+    fn visit_ternary(self, _op: &Token, left: &Expr, middle: &Expr, right: &Expr) -> Result<()> {
+        self.check_all(vec![left, middle, right].into_iter())
+    }
+
+    // Only assignments inside a local scope are checked, as those at the top
+    // level may be to globals defined by earlier input.
+    fn visit_assignment(self, name: &Token, value: &Expr) -> Result<()> {
+        self.check_assignable(name)?;
+        self.resolve(name);
+        value.accept(self.clone())?;
+        self.initialize(name);
+        Ok(())
+    }
+
+    fn visit_postfix(self, name: &Token, _op: &Token) -> Result<()> {
+        self.check_assignable(name)?;
+        self.resolve(name);
+        self.check_initialized(name)
+    }
+
+    fn visit_variable_expr(self, name: &Token) -> Result<()> {
+        self.resolve(name);
+        self.check_initialized(name)
+    }
+
+    fn visit_logical(self, left: &Expr, _op: &Token, right: &Expr) -> Result<()> {
+        left.accept(self.clone())?;
+        right.accept(self)
+    }
+
+    fn visit_call(self, callee: &Expr, _paren: &Token, args: &[Expr]) -> Result<()> {
+        callee.accept(self.clone())?;
+        self.check_all(args.iter())
+    }
+
+    fn visit_array(self, elements: &[Expr]) -> Result<()> {
+        self.check_all(elements.iter())
+    }
+
+    fn visit_index(self, object: &Expr, _bracket: &Token, index: &Expr) -> Result<()> {
+        object.accept(self.clone())?;
+        index.accept(self)
+    }
+
+    fn visit_slice(self, object: &Expr, _bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) -> Result<()> {
+        object.accept(self.clone())?;
+        self.check_all(start.iter().chain(end.iter()).map(|xp| &**xp))
+    }
+
+    fn visit_map(self, _brace: &Token, entries: &[(Expr, Expr)]) -> Result<()> {
+        self.check_all(entries.iter().flat_map(|(k, v)| vec![k, v]))
+    }
+
+    fn visit_set_index(self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> Result<()> {
+        self.check_all(vec![object, index, value].into_iter())
+    }
+
+    fn visit_get(self, object: &Expr, _name: &Token) -> Result<()> {
+        object.accept(self)
+    }
+
+    fn visit_set(self, object: &Expr, _name: &Token, value: &Expr) -> Result<()> {
+        object.accept(self.clone())?;
+        value.accept(self)
+    }
+
+    fn visit_this(self, keyword: &Token) -> Result<()> {
+        self.resolve(keyword);
+        Ok(())
+    }
+
+    fn visit_super(self, keyword: &Token, _method: &Token) -> Result<()> {
+        if self.inside_subclass {
+            self.resolve(keyword);
+            Ok(())
+        } else {
+            Err(ContextError::SuperOutsideSubclass(keyword.get_line()))
+        }
+    }
 }
 
 impl ContextCheck {
-    fn new(inside_loop: bool) -> ContextCheck {
+    fn new(inside_loop: bool, warn_empty: bool, globals: Option<Rc<HashSet<String>>>) -> ContextCheck {
         ContextCheck {
-            inside_loop
+            inside_loop,
+            continue_target: inside_loop,
+            labels: Vec::new(),
+            function: FunctionKind::None,
+            inside_subclass: false,
+            warn_empty,
+            scopes: Vec::new(),
+            function_scope: 0,
+            globals
+        }
+    }
+
+    fn enter_scope(mut self, slots: Vec<String>) -> ContextCheck {
+        self.scopes.push(Rc::new(Scope { slots, declared: RefCell::new(HashMap::new()) }));
+        self
+    }
+
+    // Globals aren't tracked here, so they can be declared again, which the
+    // prompt relies on.
+    fn declare(&self, name: &Token, initialized: bool) -> Result<()> {
+        match self.scopes.last() {
+            Some(scope) if scope.declared.borrow_mut().insert(name.get_lexeme().to_owned(), initialized).is_some() => {
+                Err(ContextError::DuplicateDeclaration(name.get_line(), name.get_lexeme().to_owned()))
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn declare_name(&self, name: &str) {
+        if let Some(scope) = self.scopes.last() {
+            scope.declared.borrow_mut().insert(name.to_owned(), true);
+        }
+    }
+
+    // The innermost local scope declaring the name, with its index.
+    fn declaring_scope(&self, name: &Token) -> Option<(usize, &Scope)> {
+        self.scopes.iter().enumerate().rev()
+            .find(|(_, scope)| scope.declared.borrow().contains_key(name.get_lexeme()))
+            .map(|(index, scope)| (index, &**scope))
+    }
+
+    // Slots are counted from the innermost scope binding the name at all,
+    // even where its declaration comes later: until the declaration runs,
+    // the interpreter finds the slot empty and searches by name instead.
+    fn resolve(&self, name: &Token) {
+        let slot = self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            scope.slots.iter().position(|slot| slot == name.get_lexeme()).map(|index| Slot::Local { depth, index })
+        });
+        name.resolve(slot.unwrap_or(Slot::Global));
+    }
+
+    // Any assignment counts, even one that might not run, so only reads that
+    // no assignment comes before are caught.
+    fn initialize(&self, name: &Token) {
+        if let Some((_, scope)) = self.declaring_scope(name) {
+            scope.declared.borrow_mut().insert(name.get_lexeme().to_owned(), true);
+        }
+    }
+
+    // A function may only run once the variable has been assigned, so reads
+    // from a function declared inside the variable's are left alone.
+    fn check_initialized(&self, name: &Token) -> Result<()> {
+        match self.declaring_scope(name) {
+            Some((index, scope)) if index >= self.function_scope && !scope.declared.borrow()[name.get_lexeme()] => {
+                Err(ContextError::ReadBeforeInit(name.get_line(), name.get_lexeme().to_owned()))
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn check_assignable(&self, name: &Token) -> Result<()> {
+        if let Some(globals) = &self.globals {
+            let declared = |scope: &Rc<Scope>| scope.declared.borrow().contains_key(name.get_lexeme());
+            if !self.scopes.is_empty() && !globals.contains(name.get_lexeme()) && !self.scopes.iter().any(declared) {
+                return Err(ContextError::AssignToUndeclared(name.get_line(), name.get_lexeme().to_owned()));
+            }
         }
+
+        Ok(())
+    }
+
+    // Functions are declared before any of the statements, as the
+    // interpreter hoists them.
+    fn check_stmts(self, stmts: &[Stmt]) -> Result<()> {
+        for stmt in stmts.iter() {
+            if let StmtKind::Function(decl) = &stmt.kind {
+                self.declare(&decl.name, true)?;
+            }
+        }
+
+        for stmt in stmts.iter() {
+            stmt.accept(self.clone())?;
+        }
+
+        Ok(())
     }
 
     fn get_inside_loop(&self) -> bool {
         self.inside_loop
     }
-}
 
-pub fn check(stmts: &Vec<Stmt>) -> Vec<ContextError> {
-    stmts.iter().filter_map(|x| {
-        let checker = ContextCheck::new(false);
+    fn enter_loop(mut self, label: &Option<Token>) -> ContextCheck {
+        self.inside_loop = true;
+        self.continue_target = true;
+        self.labels.extend(label.iter().map(|l| l.get_lexeme().to_owned()));
+        self
+    }
 
-        if let Err(er) = x.accept(checker) {
-            Some(er)
+    fn check_label(&self, line: usize, label: &Token) -> Result<()> {
+        if self.labels.iter().any(|l| l == label.get_lexeme()) {
+            Ok(())
         } else {
-            None
+            Err(ContextError::UndefinedLabel(line, label.get_lexeme().to_owned()))
         }
+    }
+
+    fn check_all<'e>(self, exprs: impl Iterator<Item = &'e Expr>) -> Result<()> {
+        for expr in exprs {
+            expr.accept(self.clone())?;
+        }
+
+        Ok(())
+    }
+
+    // Loops don't extend into function bodies, so a break there has no target.
+    // The parameters share a scope with the body.
+    fn check_function(mut self, decl: &FunctionDecl, kind: FunctionKind) -> Result<()> {
+        self.inside_loop = false;
+        self.continue_target = false;
+        self.labels.clear();
+        self.function = kind;
+        self.function_scope = self.scopes.len();
+
+        let slots = decl.params.iter().map(|param| param.get_lexeme().to_owned()).chain(scope_names(&decl.body)).collect();
+        let checker = self.enter_scope(slots);
+        for param in decl.params.iter() {
+            checker.declare(param, true)?;
+        }
+        checker.check_stmts(&decl.body)
+    }
+}
+
+// The names the statements bind in the scope they share, in the order the
+// interpreter binds them: hoisted functions first, then the other
+// declarations as they're reached.
+fn scope_names(stmts: &[Stmt]) -> Vec<String> {
+    let functions = stmts.iter().filter_map(|stmt| match &stmt.kind {
+        StmtKind::Function(decl) => Some(&decl.name),
+        _ => None
+    });
+    let others = stmts.iter().filter_map(|stmt| match &stmt.kind {
+        StmtKind::Var(name, _) | StmtKind::Class(name, _, _) => Some(name),
+        _ => None
+    });
+
+    functions.chain(others).map(|name| name.get_lexeme().to_owned()).collect()
+}
+
+// Globals are the names already defined globally before the program runs,
+// such as natives and what earlier input declared.
+pub fn check(stmts: &[Stmt], warn_empty: bool, mut globals: HashSet<String>) -> Vec<ContextError> {
+    let globals = if declare_globals(stmts, true, &mut globals) {
+        Some(Rc::new(globals))
+    } else {
+        None
+    };
+
+    stmts.iter().filter_map(|x| {
+        let checker = ContextCheck::new(false, warn_empty, globals.clone());
+
+        x.accept(checker).err()
     }).collect()
-}
\ No newline at end of file
+}
+
+// Adds the names the program declares at its top level, or with a global
+// statement anywhere. False when it imports a file, which could declare
+// anything.
+fn declare_globals(stmts: &[Stmt], top_level: bool, globals: &mut HashSet<String>) -> bool {
+    let mut known = true;
+
+    for stmt in stmts.iter() {
+        let name = match &stmt.kind {
+            StmtKind::Var(name, _) | StmtKind::Class(name, _, _) if top_level => Some(name),
+            StmtKind::Function(decl) if top_level => Some(&decl.name),
+            StmtKind::Global(name, _) => Some(name),
+            _ => None
+        };
+        globals.extend(name.map(|name| name.get_lexeme().to_owned()));
+
+        known &= match &stmt.kind {
+            StmtKind::Function(decl) => declare_globals(&decl.body, false, globals),
+            StmtKind::Class(_, _, methods) => methods.iter().all(|m| declare_globals(&m.body, false, globals)),
+            StmtKind::Block(stmts) => declare_globals(stmts, false, globals),
+            StmtKind::If(_, then, otherwise) => {
+                declare_globals(std::slice::from_ref(then), false, globals)
+                    & declare_globals(otherwise.as_slice(), false, globals)
+            },
+            StmtKind::While(_, _, body, _) | StmtKind::DoWhile(_, body, _) => declare_globals(std::slice::from_ref(body), false, globals),
+            StmtKind::Switch(_, cases, default) => {
+                cases.iter().all(|(_, stmts)| declare_globals(stmts, false, globals))
+                    & declare_globals(default.as_deref().unwrap_or(&[]), false, globals)
+            },
+            StmtKind::Import(..) => false,
+            _ => true
+        };
+    }
+
+    known
+}
+
+pub fn check_expr(expr: &Expr) -> Vec<ContextError> {
+    expr.accept(ContextCheck::new(false, false, None)).err().into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ErrorReporter;
+    use crate::tokens::Scanner;
+    use crate::parser::Parser;
+    use crate::syntax::ExprKind;
+
+    fn check(stmts: &[Stmt], warn_empty: bool) -> Vec<ContextError> {
+        super::check(stmts, warn_empty, HashSet::new())
+    }
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+        let stmts = Parser::new(tokens, &mut err_rep).parse();
+        assert!(!err_rep.had_error);
+        stmts.ok().unwrap()
+    }
+
+    #[test]
+    fn empty_statement_warning_is_optional() {
+        let stmts = parse("while (true) { ; break; }");
+        assert!(check(&stmts, false).is_empty());
+
+        let errs = check(&stmts, true);
+        assert_eq!(errs.len(), 1);
+        assert!(matches!(errs[0], ContextError::EmptyStatement(1)));
+    }
+
+    #[test]
+    fn switch_is_a_break_target() {
+        assert!(check(&parse("switch (1) { case 1: break; default: break; }"), false).is_empty());
+        assert_eq!(check(&parse("switch (1) { case 1: { } } break;"), false).len(), 1);
+    }
+
+    #[test]
+    fn return_needs_a_function() {
+        assert!(check(&parse("fun f() { while (true) { return 1; } }"), false).is_empty());
+        assert!(matches!(check(&parse("return 1;"), false)[..], [ContextError::ReturnOutsideFunction(1)]));
+        assert!(matches!(check(&parse("while (true) { fun f() { break; } }"), false)[..], [ContextError::BreakOutsideLoop(1)]));
+        assert!(matches!(check(&parse("class A { init() { return 1; } }"), false)[..], [ContextError::ReturnValueFromInitializer(1)]));
+        assert!(check(&parse("class A { init() { fun f() { return 1; } return; } }"), false).is_empty());
+    }
+
+    #[test]
+    fn super_needs_a_subclass() {
+        assert!(check(&parse("class A { } class B < A { f() { return super.f(); } }"), false).is_empty());
+        assert!(matches!(check(&parse("class A { f() { super.f(); } }"), false)[..], [ContextError::SuperOutsideSubclass(1)]));
+        assert!(matches!(check(&parse("print super.f;"), false)[..], [ContextError::SuperOutsideSubclass(1)]));
+    }
+
+    #[test]
+    fn labels_name_enclosing_loops() {
+        assert!(check(&parse("outer: while (true) { for (;;) { break outer; } continue outer; }"), false).is_empty());
+        assert!(check(&parse("a: do { switch (1) { case 1: continue a; } } while (true);"), false).is_empty());
+        assert!(matches!(&check(&parse("a: while (true) { } while (true) { break a; }"), false)[..], [ContextError::UndefinedLabel(1, label)] if label == "a"));
+        assert!(matches!(check(&parse("a: while (true) { fun f() { continue a; } }"), false)[..], [ContextError::UndefinedLabel(1, _)]));
+        assert!(matches!(check(&parse("switch (1) { case 1: continue; }"), false)[..], [ContextError::ContinueOutsideLoop(1)]));
+    }
+
+    #[test]
+    fn assignments_need_a_declaration() {
+        assert!(check(&parse("x = 1; { var a; a = 1; { a = 2; } } fun f(p) { p = 1; f = nil; }"), false).is_empty());
+        assert!(check(&parse("var g; { g = 1; } fun f() { h = 2; } class C { m() { h = 3; } } var h;"), false).is_empty());
+        assert!(check(&parse("fun f() { global late; } { late = 1; }"), false).is_empty());
+        assert!(matches!(&check(&parse("{ var a; }\n{ a = 2; }"), false)[..], [ContextError::AssignToUndeclared(2, name)] if name == "a"));
+        assert!(matches!(check(&parse("fun f() { y += 1; }"), false)[..], [ContextError::AssignToUndeclared(1, _)]));
+
+        let globals: HashSet<String> = vec!["y".to_owned()].into_iter().collect();
+        assert!(super::check(&parse("fun f() { y += 1; }"), false, globals).is_empty());
+        assert!(check(&parse("import \"a.lox\"; fun f() { y = 1; }"), false).is_empty());
+    }
+
+    #[test]
+    fn locals_are_declared_once_per_scope() {
+        assert!(check(&parse("var a = 1; var a = 2; { var a; { var a; } } fun f(a) { { var a; } }"), false).is_empty());
+        assert!(matches!(&check(&parse("{ var a = 1;\nvar a = 2; }"), false)[..], [ContextError::DuplicateDeclaration(2, name)] if name == "a"));
+        assert!(matches!(check(&parse("fun f(a, a) { }"), false)[..], [ContextError::DuplicateDeclaration(1, _)]));
+        assert!(matches!(check(&parse("fun f(a) { var a; }"), false)[..], [ContextError::DuplicateDeclaration(1, _)]));
+        assert!(matches!(check(&parse("{ class A { } fun A() { } }"), false)[..], [ContextError::DuplicateDeclaration(1, _)]));
+    }
+
+    fn variables(expr: &Expr, slots: &mut Vec<Option<Slot>>) {
+        match &expr.kind {
+            ExprKind::Binary(left, _, right) => {
+                variables(left, slots);
+                variables(right, slots);
+            },
+            ExprKind::Var(name) => slots.push(name.slot()),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn names_resolve_to_slots() {
+        let stmts = parse("var g; fun f(a, b) { var c = 1; fun h() { } { var d = 2; print b + c + d + h + g; } }");
+        assert!(check(&stmts, false).is_empty());
+
+        let mut slots = Vec::new();
+        match &stmts[1].kind {
+            StmtKind::Function(decl) => match &decl.body[2].kind {
+                StmtKind::Block(body) => match &body[1].kind {
+                    StmtKind::Print(expr) => variables(expr, &mut slots),
+                    _ => panic!("expected a print")
+                },
+                _ => panic!("expected a block")
+            },
+            _ => panic!("expected a function")
+        }
+
+        let local = |depth, index| Some(Slot::Local { depth, index });
+        assert_eq!(slots, vec![local(1, 1), local(1, 3), local(0, 0), local(1, 2), Some(Slot::Global)]);
+    }
+
+    #[test]
+    fn reads_before_any_assignment_are_caught() {
+        assert!(matches!(&check(&parse("{ var a;\nprint a; }"), false)[..], [ContextError::ReadBeforeInit(2, name)] if name == "a"));
+        assert!(matches!(check(&parse("{ var a; a = a + 1; }"), false)[..], [ContextError::ReadBeforeInit(1, _)]));
+        assert!(matches!(check(&parse("{ var a; { a++; } }"), false)[..], [ContextError::ReadBeforeInit(1, _)]));
+
+        assert!(check(&parse("var a; print a;"), false).is_empty());
+        assert!(check(&parse("{ var a; if (true) a = 1; print a; }"), false).is_empty());
+        assert!(check(&parse("{ var a; fun f() { return a; } a = 1; print f(); }"), false).is_empty());
+        assert!(check(&parse("{ var a; { var a = 1; print a; } }"), false).is_empty());
+    }
+}
@@ -1,109 +1,308 @@
-
 use crate::tokens::Token;
 use crate::tokens::Literal;
+use crate::symbols::Symbol;
+use crate::symbols::Slot;
 use crate::interpreter::Result;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
 use crate::interpreter::InterpreterError;
 use crate::interpreter::RuntimeError;
 
 pub struct Stack {
-	envs: Vec<Environment>,
-	current: Environment,
+	globals: Rc<RefCell<Environment>>,
+	current: Rc<RefCell<Environment>>,
 }
 
 impl Stack {
 	pub fn new() -> Stack {
+		let globals = Rc::new(RefCell::new(Environment::new(None)));
+
 		Stack {
-			envs: Vec::new(),
-			current: Environment::new()
+			current: globals.clone(),
+			globals
 		}
 	}
 
-	pub fn push_new(&mut self) {
-		let old = std::mem::replace(&mut self.current, Environment::new());
-		self.envs.push(old);
+	// Enters a fresh scope inside the current one, handing back the scope to
+	// return to with restore.
+	pub fn push_new(&mut self) -> Rc<RefCell<Environment>> {
+		let env = Rc::new(RefCell::new(Environment::new(Some(self.current.clone()))));
+		std::mem::replace(&mut self.current, env)
 	}
 
-	pub fn restore_old(&mut self) {
-		let old = self.envs.pop();
-		self.current = old.unwrap();
+	pub fn capture(&self) -> Rc<RefCell<Environment>> {
+		self.current.clone()
 	}
 
-	pub fn define(&mut self, name: String, value: Option<Literal>) {
-		self.current.define(name, value)
+	// Enters a fresh scope inside a closure's environment, handing back the
+	// scope to return to with restore.
+	pub fn push_closure(&mut self, closure: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+		let env = Rc::new(RefCell::new(Environment::new(Some(closure))));
+		std::mem::replace(&mut self.current, env)
+	}
+
+	// Makes the globals the current scope, handing back the scope to return
+	// to with restore.
+	pub fn enter_globals(&mut self) -> Rc<RefCell<Environment>> {
+		std::mem::replace(&mut self.current, self.globals.clone())
+	}
+
+	pub fn restore(&mut self, previous: Rc<RefCell<Environment>>) {
+		self.current = previous;
+	}
+
+	pub fn define(&mut self, name: Symbol, value: Option<Literal>) {
+		self.current.borrow_mut().define(name, value)
+	}
+
+	// Binds the name in the global environment no matter how deeply the
+	// current scope is nested. Declaring without a value keeps an existing
+	// global's value.
+	pub fn define_global(&mut self, name: Symbol, value: Option<Literal>) {
+		let mut globals = self.globals.borrow_mut();
+
+		if value.is_some() || !globals.values.contains_key(&name) {
+			globals.define(name, value)
+		}
+	}
+
+	pub fn global_names(&self) -> HashSet<String> {
+		self.globals.borrow().values.keys().map(|sym| sym.name().to_string()).collect()
 	}
 
 	pub fn assign(&mut self, name: &Token, value: Literal) -> Result<()> {
-		if let Ok(_) = self.current.assign(name, value.clone()) {
-			Ok(())
-		} else {
-			for item in self.envs.iter_mut().rev() {
-				if let Ok(_) = item.assign(name,value.clone()) {
-					return Ok(())
-				}
+		let assigned = match name.slot() {
+			Some(Slot::Local { depth, index }) => self.ancestor(depth).and_then(|env| env.borrow_mut().assign_slot(index, name.symbol(), &value)),
+			Some(Slot::Global) => self.globals.borrow_mut().assign(name, value.clone()).ok(),
+			None => None
+		};
+		if assigned.is_some() {
+			return Ok(())
+		}
+
+		let mut env = Some(self.current.clone());
+
+		while let Some(item) = env {
+			if item.borrow_mut().assign(name, value.clone()).is_ok() {
+				return Ok(())
 			}
 
-			Err(RuntimeError::InterpreterError(<InterpreterError>::new(name, " Undefined variable")))
+			env = item.borrow().enclosing.clone();
 		}
+
+		Err(RuntimeError::InterpreterError(<InterpreterError>::new(name, &undefined(name))))
 	}
 
+	// A name declared without a value is uninitialized, wherever the scope
+	// declaring it sits, and shadows any outer name. Only a name no enclosing
+	// scope declares is undefined.
 	pub fn get(&self, tk: &Token) -> Result<Literal> {
-		let lt = self.get_helper(tk)?;
+		let binding = match tk.slot() {
+			Some(Slot::Local { depth, index }) => self.ancestor(depth).and_then(|env| env.borrow().slot_binding(index, tk.symbol())),
+			Some(Slot::Global) => self.globals.borrow().binding(tk.symbol()),
+			None => None
+		};
 
-		if let Some(lt) = lt {
-			Ok(lt)
-		} else {
-			Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Uninitialized variable")))
+		match binding.or_else(|| self.search(tk.symbol())) {
+			Some(Some(lt)) => Ok(lt),
+			Some(None) => {
+				let msg = format!("Variable '{}' used before initialization", tk.get_lexeme());
+				Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, &msg)))
+			},
+			None => Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, &undefined(tk))))
 		}
 	}
 
-	fn get_helper(&self, tk: &Token) -> Result<Option<Literal>> {
-		if let Ok(val) = self.current.get(tk) {
-			Ok(val)
-		} else {
-			for item in self.envs.iter().rev() {
-				if let Ok(val) = item.get(tk) {
-					return Ok(val);
-				}
+	// Looks for the name scope by scope, for a name the checker didn't
+	// resolve, or whose slot isn't bound yet because its declaration hasn't
+	// run.
+	fn search(&self, name: Symbol) -> Option<Option<Literal>> {
+		let mut env = Some(self.current.clone());
+
+		while let Some(item) = env {
+			if let Some(binding) = item.borrow().binding(name) {
+				return Some(binding);
 			}
 
-			Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Undefined variable")))
+			env = item.borrow().enclosing.clone();
 		}
+
+		None
 	}
 
-}
+	// The scope the given number of scopes out from the current one.
+	fn ancestor(&self, depth: usize) -> Option<Rc<RefCell<Environment>>> {
+		let mut env = self.current.clone();
 
-struct Environment {
-	values: HashMap<String, Option<Literal>>
-}
+		for _ in 0..depth {
+			let enclosing = env.borrow().enclosing.clone()?;
+			env = enclosing;
+		}
 
+		Some(env)
+	}
+
+}
 
+// Each scope links to the one enclosing it, ending at the globals. The
+// globals are found by name; a local scope keeps its bindings in the order
+// they were declared, which is the index the checker resolves names to.
+pub struct Environment {
+	values: HashMap<Symbol, Option<Literal>>,
+	slots: Vec<(Symbol, Option<Literal>)>,
+	enclosing: Option<Rc<RefCell<Environment>>>
+}
 
 impl Environment {
-	pub fn new() -> Environment {
+	pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Environment {
 		Environment {
-			values: HashMap::new()
+			values: HashMap::new(),
+			slots: Vec::new(),
+			enclosing
 		}
 	}
 
-	pub fn define(&mut self, name: String, value: Option<Literal>) {
-		self.values.insert(name, value);
+	// A scope holding a single binding, used to give methods their `this`.
+	pub fn bind(enclosing: Rc<RefCell<Environment>>, name: &str, value: Literal) -> Rc<RefCell<Environment>> {
+		let mut env = Environment::new(Some(enclosing));
+		env.define(Symbol::intern(name), Some(value));
+		Rc::new(RefCell::new(env))
 	}
 
-	pub fn get(&self, tk: &Token) -> Result<Option<Literal>> {
-		if let Some(val) = self.values.get(tk.get_lexeme()) {
-			Ok(val.clone())
+	// Defining a name the scope already binds replaces its value in place,
+	// as when a hoisted function's declaration is reached.
+	pub fn define(&mut self, name: Symbol, value: Option<Literal>) {
+		if self.enclosing.is_none() {
+			self.values.insert(name, value);
+		} else if let Some(slot) = self.slots.iter_mut().find(|(sym, _)| *sym == name) {
+			slot.1 = value;
 		} else {
-			Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Undefined variable")))
+			self.slots.push((name, value));
+		}
+	}
+
+	pub fn lookup(&self, name: &str) -> Option<Literal> {
+		self.binding(Symbol::intern(name)).flatten()
+	}
+
+	// None when this scope doesn't declare the name, Some(None) when it does
+	// without a value.
+	fn binding(&self, name: Symbol) -> Option<Option<Literal>> {
+		match self.enclosing {
+			None => self.values.get(&name).cloned(),
+			Some(_) => self.slots.iter().find(|(sym, _)| *sym == name).map(|(_, value)| value.clone())
+		}
+	}
+
+	// The binding at a resolved index, if the name's declaration has run.
+	fn slot_binding(&self, index: usize, name: Symbol) -> Option<Option<Literal>> {
+		match self.slots.get(index) {
+			Some((sym, value)) if *sym == name => Some(value.clone()),
+			_ => None
+		}
+	}
+
+	fn assign_slot(&mut self, index: usize, name: Symbol, value: &Literal) -> Option<()> {
+		match self.slots.get_mut(index) {
+			Some((sym, slot)) if *sym == name => {
+				*slot = Some(value.clone());
+				Some(())
+			},
+			_ => None
 		}
 	}
 
 	pub fn assign(&mut self, name: &Token, value: Literal) -> Result<()> {
-		if !self.values.contains_key(name.get_lexeme()) {
-			Err(RuntimeError::InterpreterError(<InterpreterError>::new(name, " Undefined variable")))
-		} else {
-			self.values.insert(name.get_lexeme().to_owned(), Some(value));
-			Ok(())
+		let slot = match self.enclosing {
+			None => self.values.get_mut(&name.symbol()),
+			Some(_) => self.slots.iter_mut().find(|(sym, _)| *sym == name.symbol()).map(|(_, value)| value)
+		};
+
+		match slot {
+			Some(slot) => {
+				*slot = Some(value);
+				Ok(())
+			},
+			None => Err(RuntimeError::InterpreterError(<InterpreterError>::new(name, &undefined(name))))
+		}
+	}
+}
+
+fn undefined(name: &Token) -> String {
+	format!("Undefined variable '{}'", name.get_lexeme())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::tokens::TokenType;
+
+	fn ident(name: &str) -> Token {
+		Token::new(TokenType::Identifier, name.to_owned(), 1, 1)
+	}
+
+	fn number(stack: &Stack, name: &str) -> f64 {
+		match stack.get(&ident(name)) {
+			Ok(Literal::Number(x)) => x,
+			_ => panic!("expected {} to hold a number", name),
 		}
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn globals_visible_in_and_after_blocks() {
+		let mut stack = Stack::new();
+		stack.define(Symbol::intern("a"), Some(Literal::Number(1.0)));
+
+		let globals = stack.push_new();
+		stack.push_new();
+		assert_eq!(number(&stack, "a"), 1.0);
+		assert!(stack.assign(&ident("a"), Literal::Number(2.0)).is_ok());
+		stack.restore(globals);
+
+		assert_eq!(number(&stack, "a"), 2.0);
+	}
+
+	#[test]
+	fn block_scopes_shadow_and_disappear() {
+		let mut stack = Stack::new();
+		stack.define(Symbol::intern("a"), Some(Literal::Number(1.0)));
+
+		let globals = stack.push_new();
+		stack.define(Symbol::intern("a"), Some(Literal::Number(2.0)));
+		stack.define(Symbol::intern("b"), Some(Literal::Number(3.0)));
+		stack.define_global(Symbol::intern("c"), Some(Literal::Number(4.0)));
+		assert_eq!(number(&stack, "a"), 2.0);
+		stack.restore(globals);
+
+		assert_eq!(number(&stack, "a"), 1.0);
+		assert_eq!(number(&stack, "c"), 4.0);
+		assert!(stack.get(&ident("b")).is_err());
+	}
+
+	fn get_err(stack: &Stack, name: &str) -> String {
+		match stack.get(&ident(name)) {
+			Err(RuntimeError::InterpreterError(err)) => err.get_reason().to_owned(),
+			_ => panic!("expected getting {} to fail", name),
+		}
+	}
+
+	#[test]
+	fn undefined_and_uninitialized_differ_at_any_depth() {
+		let mut stack = Stack::new();
+		stack.define(Symbol::intern("a"), None);
+		stack.define(Symbol::intern("b"), Some(Literal::Number(1.0)));
+
+		let globals = stack.push_new();
+		stack.define(Symbol::intern("b"), None);
+		stack.push_new();
+		assert_eq!(get_err(&stack, "a"), "Variable 'a' used before initialization");
+		assert_eq!(get_err(&stack, "b"), "Variable 'b' used before initialization");
+		assert_eq!(get_err(&stack, "c"), "Undefined variable 'c'");
+		stack.restore(globals);
+
+		assert_eq!(number(&stack, "b"), 1.0);
+		assert_eq!(get_err(&stack, "c"), "Undefined variable 'c'");
+	}
+}
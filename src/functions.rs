@@ -1,34 +1,436 @@
-use crate::syntax::Expr;
+use crate::interpreter;
 use crate::interpreter::Interpreter;
+use crate::interpreter::InterpreterError;
+use crate::interpreter::RuntimeError;
 use crate::tokens::Literal;
+use crate::tokens::Token;
 use crate::interpreter::Result;
+use crate::environment::Environment;
+use crate::syntax::FunctionDecl;
+use crate::classes::Instance;
+use crate::classes::LoxClass;
+use crate::host::HostFunction;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type NativeFn = fn(&mut Interpreter, &Token, Vec<Literal>) -> Result<Literal>;
+
+// How many arguments a callable takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+	Fixed(usize),
+	AtLeast(usize),
+	// Both ends are included.
+	Range(usize, usize)
+}
+
+impl Arity {
+	pub fn accepts(self, count: usize) -> bool {
+		match self {
+			Arity::Fixed(n) => count == n,
+			Arity::AtLeast(min) => count >= min,
+			Arity::Range(min, max) => (min..=max).contains(&count),
+		}
+	}
+}
+
+// Reads as the count in "Expected _ arguments".
+impl std::fmt::Display for Arity {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Arity::Fixed(n) => write!(f, "{}", n),
+			Arity::AtLeast(min) => write!(f, "at least {}", min),
+			Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+		}
+	}
+}
 
 pub enum Callable {
-	Static(StaticFunction)
+	Native(NativeFunction),
+	Host(String, Box<dyn HostFunction>),
+	Function(LoxFunction),
+	Class(Rc<LoxClass>)
 }
 
 impl Callable {
-	pub fn from(expr: &Expr) -> Result<Callable> {
+	pub fn from(value: Literal, paren: &Token) -> Result<Rc<Callable>> {
+		match value {
+			Literal::Callable(func) => Ok(func),
+			_ => Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "Can only call functions and classes"))),
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		match self {
+			Callable::Native(native) => native.name,
+			Callable::Host(name, _) => name,
+			Callable::Function(func) => func.decl.name.get_lexeme(),
+			Callable::Class(class) => class.name(),
+		}
+	}
+}
 
+impl std::fmt::Debug for Callable {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.to_string())
 	}
 }
 
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Callable {
+	fn to_string(&self) -> String {
+		match self {
+			Callable::Native(native) => format!("<native fn {}>", native.name),
+			Callable::Host(name, _) => format!("<native fn {}>", name),
+			Callable::Function(func) => format!("<fn {}>", func.decl.name.get_lexeme()),
+			Callable::Class(class) => format!("<class {}>", class.name()),
+		}
+	}
+}
 
 impl LoxCalls for Callable {
-	fn call(&self, intepreter: &mut Interpreter, args: Vec<Literal>) -> Result<Literal> {
+	fn call(&self, interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+		match self {
+			Callable::Native(native) => (native.func)(interpreter, paren, args),
+			Callable::Host(_, func) => func.call(args).map_err(|msg| RuntimeError::InterpreterError(InterpreterError::new(paren, &msg))),
+			Callable::Function(func) => func.call(interpreter, paren, args),
+			Callable::Class(class) => {
+				let instance = Rc::new(RefCell::new(Instance::new(class.clone())));
+
+				if let Some(init) = class.find_method("init") {
+					init.bind(Literal::Instance(instance.clone())).call(interpreter, paren, args)?;
+				}
 
+				Ok(Literal::Instance(instance))
+			},
+		}
 	}
 
-	fn arity(&self) -> usize {
-		1
+	fn arity(&self) -> Arity {
+		match self {
+			Callable::Native(native) => native.arity,
+			Callable::Host(_, func) => Arity::Fixed(func.arity()),
+			Callable::Function(func) => func.arity(),
+			Callable::Class(class) => class.find_method("init").map_or(Arity::Fixed(0), |init| init.arity()),
+		}
 	}
 }
 
 pub trait LoxCalls {
-	fn call(&self, intepreter: &mut Interpreter, args: Vec<Literal>) -> Result<Literal>;
-	fn arity(&self) -> usize;
+	fn call(&self, interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal>;
+	fn arity(&self) -> Arity;
+}
+
+pub struct LoxFunction {
+	decl: Rc<FunctionDecl>,
+	closure: Rc<RefCell<Environment>>,
+	is_initializer: bool
+}
+
+impl LoxFunction {
+	pub fn new(decl: Rc<FunctionDecl>, closure: Rc<RefCell<Environment>>, is_initializer: bool) -> LoxFunction {
+		LoxFunction {
+			decl,
+			closure,
+			is_initializer
+		}
+	}
+
+	pub fn bind(&self, instance: Literal) -> LoxFunction {
+		LoxFunction::new(self.decl.clone(), Environment::bind(self.closure.clone(), "this", instance), self.is_initializer)
+	}
+}
+
+impl LoxCalls for LoxFunction {
+	// Initializers always hand back the instance, even on an early return.
+	fn call(&self, interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+		let value = interpreter.call_function(&self.decl, self.closure.clone(), args)?;
+
+		if self.is_initializer {
+			Ok(self.closure.borrow().lookup("this").unwrap_or(Literal::Nil))
+		} else {
+			Ok(value)
+		}
+	}
+
+	fn arity(&self) -> Arity {
+		Arity::Fixed(self.decl.params.len())
+	}
+}
+
+pub struct NativeFunction {
+	name: &'static str,
+	arity: Arity,
+	func: NativeFn
+}
+
+impl NativeFunction {
+	fn with_arity(name: &'static str, arity: Arity, func: NativeFn) -> NativeFunction {
+		NativeFunction {
+			name,
+			arity,
+			func
+		}
+	}
+
+	fn new(name: &'static str, arity: usize, func: NativeFn) -> NativeFunction {
+		NativeFunction::with_arity(name, Arity::Fixed(arity), func)
+	}
+
+	// Takes between min_arity and arity arguments.
+	fn optional(name: &'static str, min_arity: usize, arity: usize, func: NativeFn) -> NativeFunction {
+		NativeFunction::with_arity(name, Arity::Range(min_arity, arity), func)
+	}
+
+	fn variadic(name: &'static str, min_arity: usize, func: NativeFn) -> NativeFunction {
+		NativeFunction::with_arity(name, Arity::AtLeast(min_arity), func)
+	}
+}
+
+pub fn natives() -> Vec<Callable> {
+	vec![
+		Callable::Native(NativeFunction::new("len", 1, len)),
+		Callable::Native(NativeFunction::new("write", 1, write)),
+		Callable::Native(NativeFunction::new("eprint", 1, eprint)),
+		Callable::Native(NativeFunction::optional("input", 0, 1, input)),
+		Callable::Native(NativeFunction::optional("read_line", 0, 1, input)),
+		Callable::Native(NativeFunction::new("number", 1, number)),
+		Callable::Native(NativeFunction::new("string", 1, string)),
+		Callable::Native(NativeFunction::new("str", 1, string)),
+		Callable::Native(NativeFunction::new("num", 1, num)),
+		Callable::Native(NativeFunction::new("bool", 1, bool)),
+		Callable::Native(NativeFunction::new("sqrt", 1, sqrt)),
+		Callable::Native(NativeFunction::new("is_nan", 1, is_nan)),
+		Callable::Native(NativeFunction::new("floor", 1, floor)),
+		Callable::Native(NativeFunction::new("ceil", 1, ceil)),
+		Callable::Native(NativeFunction::new("round", 1, round)),
+		Callable::Native(NativeFunction::new("abs", 1, abs)),
+		Callable::Native(NativeFunction::new("min", 2, min)),
+		Callable::Native(NativeFunction::new("max", 2, max)),
+		Callable::Native(NativeFunction::new("pow", 2, pow)),
+		Callable::Native(NativeFunction::optional("assert", 1, 2, assert)),
+		Callable::Native(NativeFunction::new("random", 0, random)),
+		Callable::Native(NativeFunction::new("rand", 0, random)),
+		Callable::Native(NativeFunction::new("randint", 2, randint)),
+		Callable::Native(NativeFunction::new("rand_int", 2, randint)),
+		Callable::Native(NativeFunction::new("seed", 1, seed)),
+		Callable::Native(NativeFunction::new("typeof", 1, type_of)),
+		Callable::Native(NativeFunction::new("type", 1, type_of)),
+		Callable::Native(NativeFunction::variadic("format", 1, format)),
+		Callable::Native(NativeFunction::variadic("fmt", 1, format)),
+	]
+}
+
+fn len(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	match &args[0] {
+		Literal::String(s) => Ok(Literal::Number(s.chars().count() as f64)),
+		Literal::Array(items) => Ok(Literal::Number(items.borrow().len() as f64)),
+		Literal::Map(entries) => Ok(Literal::Number(entries.borrow().len() as f64)),
+		_ => Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "len() expects a string, array or map"))),
+	}
+}
+
+// Like print, but without the trailing newline. Flushes so partial lines show
+// up straight away.
+fn write(interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	interpreter.write_output(&args[0].to_string());
+	interpreter.flush_output();
+	Ok(Literal::Nil)
+}
+
+// Like print, but to the error output, so diagnostics stay apart from data.
+fn eprint(interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	interpreter.write_error_output(&format!("{}\n", args[0]));
+	Ok(Literal::Nil)
+}
+
+// Reads a line of input, or nil once input runs out. A string argument is
+// written out first as a prompt.
+fn input(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	match args.first() {
+		Some(Literal::String(prompt)) => interpreter.write_output(prompt),
+		Some(_) => return Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "input() expects a string prompt"))),
+		None => {}
+	}
+	interpreter.flush_output();
+	Ok(interpreter.read_input_line().map_or(Literal::Nil, Literal::String))
+}
+
+// Parses a string into a number. Text that isn't a number gives nil, so
+// scripts can check input without stopping.
+fn number(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	match &args[0] {
+		Literal::String(s) => Ok(s.trim().parse().ok().filter(|x: &f64| x.is_finite()).map_or(Literal::Nil, Literal::Number)),
+		Literal::Number(x) => Ok(Literal::Number(*x)),
+		_ => Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "number() expects a string or number"))),
+	}
+}
+
+// Like number, but text that isn't a number is an error.
+fn num(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	match number(interpreter, paren, args)? {
+		Literal::Nil => Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "Cannot convert to number"))),
+		x => Ok(x),
+	}
+}
+
+fn string(_interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	Ok(Literal::String(args[0].to_string()))
+}
+
+fn bool(_interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	Ok(Literal::Boolean(interpreter::is_truthy(&args[0])))
+}
+
+fn numbers(name: &str, paren: &Token, args: &[Literal]) -> Result<Vec<f64>> {
+	args.iter().map(|arg| match arg {
+		Literal::Number(x) => Ok(*x),
+		_ => Err(RuntimeError::InterpreterError(InterpreterError::new(paren, &format!("{}() expects numbers", name)))),
+	}).collect()
+}
+
+fn sqrt(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("sqrt", paren, &args)?;
+	Ok(Literal::Number(x[0].sqrt()))
+}
+
+// NaN never equals itself, so this is the way to test for it.
+fn is_nan(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("is_nan", paren, &args)?;
+	Ok(Literal::Boolean(x[0].is_nan()))
+}
+
+fn floor(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("floor", paren, &args)?;
+	Ok(Literal::Number(x[0].floor()))
+}
+
+fn ceil(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("ceil", paren, &args)?;
+	Ok(Literal::Number(x[0].ceil()))
+}
+
+// Halves round away from zero.
+fn round(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("round", paren, &args)?;
+	Ok(Literal::Number(x[0].round()))
+}
+
+fn abs(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("abs", paren, &args)?;
+	Ok(Literal::Number(x[0].abs()))
+}
+
+fn min(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("min", paren, &args)?;
+	Ok(Literal::Number(x[0].min(x[1])))
+}
+
+fn max(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("max", paren, &args)?;
+	Ok(Literal::Number(x[0].max(x[1])))
+}
+
+fn pow(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = numbers("pow", paren, &args)?;
+	Ok(Literal::Number(x[0].powf(x[1])))
+}
+
+// An optional second argument is added to the error.
+fn assert(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	if interpreter::is_truthy(&args[0]) {
+		return Ok(Literal::Nil);
+	}
+
+	let reason = match args.get(1) {
+		Some(message) => format!("Assertion failed: {}", message),
+		None => "Assertion failed".to_owned(),
+	};
+	Err(RuntimeError::InterpreterError(InterpreterError::new(paren, &reason)))
+}
+
+fn integers(name: &str, paren: &Token, args: &[Literal]) -> Result<Vec<i64>> {
+	numbers(name, paren, args)?.into_iter().map(|x| if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 {
+		Ok(x as i64)
+	} else {
+		Err(RuntimeError::InterpreterError(InterpreterError::new(paren, &format!("{}() expects integers", name))))
+	}).collect()
+}
+
+// A float in [0, 1), built from the top 53 bits so every value is exact.
+fn random(interpreter: &mut Interpreter, _paren: &Token, _args: Vec<Literal>) -> Result<Literal> {
+	Ok(Literal::Number((interpreter.next_random() >> 11) as f64 / (1u64 << 53) as f64))
 }
 
-struct StaticFunction {
+// An integer between lo and hi, both included.
+fn randint(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = integers("randint", paren, &args)?;
+	if x[0] > x[1] {
+		return Err(RuntimeError::InterpreterError(InterpreterError::new(paren, "randint() needs lo <= hi")));
+	}
+
+	let span = (x[1] as i128 - x[0] as i128 + 1) as u128;
+	let offset = (interpreter.next_random() as u128 % span) as i128;
+	Ok(Literal::Number((x[0] as i128 + offset) as f64))
+}
+
+fn seed(interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let x = integers("seed", paren, &args)?;
+	interpreter.seed_random(x[0] as u64);
+	Ok(Literal::Nil)
+}
+
+fn type_of(_interpreter: &mut Interpreter, _paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let name = match &args[0] {
+		Literal::Number(_) => "number",
+		Literal::String(_) => "string",
+		Literal::Boolean(_) => "boolean",
+		Literal::Nil => "nil",
+		Literal::Array(_) => "array",
+		Literal::Map(_) => "map",
+		Literal::Callable(func) => match **func {
+			Callable::Class(_) => "class",
+			Callable::Native(_) | Callable::Host(..) | Callable::Function(_) => "function",
+		},
+		Literal::Instance(_) => "instance",
+	};
+	Ok(Literal::String(name.to_owned()))
+}
 
-}
\ No newline at end of file
+// Fills each {} in the template with the next value, in order. {{ and }}
+// stand for literal braces.
+fn format(_interpreter: &mut Interpreter, paren: &Token, args: Vec<Literal>) -> Result<Literal> {
+	let fail = |msg: &str| Err(RuntimeError::InterpreterError(InterpreterError::new(paren, msg)));
+	let mut args = args.into_iter();
+	let template = match args.next() {
+		Some(Literal::String(template)) => template,
+		_ => return fail("format() expects a string template"),
+	};
+
+	let mut out = String::new();
+	let mut chars = template.chars().peekable();
+	while let Some(c) = chars.next() {
+		match (c, chars.peek()) {
+			('{', Some('{')) | ('}', Some('}')) => {
+				chars.next();
+				out.push(c);
+			},
+			('{', Some('}')) => {
+				chars.next();
+				match args.next() {
+					Some(value) => out.push_str(&value.to_string()),
+					None => return fail("format() has more placeholders than values"),
+				}
+			},
+			('{', _) | ('}', _) => return fail("format() found an unmatched brace; write {{ or }} for a literal one"),
+			_ => out.push(c),
+		}
+	}
+
+	if args.next().is_some() {
+		return fail("format() has more values than placeholders");
+	}
+
+	Ok(Literal::String(out))
+}
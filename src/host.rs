@@ -0,0 +1,114 @@
+use crate::tokens::Literal;
+use crate::tokens::MapKey;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+// A function the embedding program provides to scripts. Unlike the natives it
+// can hold state, such as a handle to something outside the interpreter. An
+// Err becomes a runtime error at the call.
+pub trait HostFunction {
+	fn arity(&self) -> usize;
+	fn call(&self, args: Vec<Literal>) -> Result<Literal, String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostValue {
+	Number(f64),
+	String(String),
+	Boolean(bool),
+	Array(Vec<HostValue>),
+	Map(HashMap<String, HostValue>),
+	Nil
+}
+
+#[derive(Debug)]
+pub struct ConversionError {
+	msg: String
+}
+
+impl ConversionError {
+	pub fn get_msg(&self) -> &str {
+		&self.msg
+	}
+}
+
+impl TryFrom<Literal> for HostValue {
+	type Error = ConversionError;
+
+	fn try_from(lit: Literal) -> Result<HostValue, ConversionError> {
+		match lit {
+			Literal::Number(x) => Ok(HostValue::Number(x)),
+			Literal::String(s) => Ok(HostValue::String(s)),
+			Literal::Boolean(b) => Ok(HostValue::Boolean(b)),
+			Literal::Nil => Ok(HostValue::Nil),
+			Literal::Array(items) => {
+				let items = items.borrow().iter().cloned().map(HostValue::try_from).collect::<Result<Vec<_>, _>>()?;
+				Ok(HostValue::Array(items))
+			},
+			// Host maps are keyed by strings, so number keys are written out.
+			Literal::Map(entries) => {
+				let entries = entries.borrow().iter()
+					.map(|(k, v)| HostValue::try_from(v.clone()).map(|v| (k.to_string(), v)))
+					.collect::<Result<HashMap<_, _>, _>>()?;
+				Ok(HostValue::Map(entries))
+			},
+			Literal::Callable(func) => Err(ConversionError {
+				msg: format!("Cannot convert {} to a host value", func.to_string())
+			}),
+			Literal::Instance(_) => Err(ConversionError {
+				msg: format!("Cannot convert {} to a host value", lit)
+			}),
+		}
+	}
+}
+
+impl From<HostValue> for Literal {
+	fn from(value: HostValue) -> Literal {
+		match value {
+			HostValue::Number(x) => Literal::Number(x),
+			HostValue::String(s) => Literal::String(s),
+			HostValue::Boolean(b) => Literal::Boolean(b),
+			HostValue::Nil => Literal::Nil,
+			HostValue::Array(items) => {
+				let items = items.into_iter().map(Literal::from).collect();
+				Literal::Array(Rc::new(RefCell::new(items)))
+			},
+			HostValue::Map(entries) => {
+				let entries = entries.into_iter().map(|(k, v)| (MapKey::String(k), Literal::from(v))).collect();
+				Literal::Map(Rc::new(RefCell::new(entries)))
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn literal_to_host() {
+		assert_eq!(HostValue::try_from(Literal::Number(1.5)).unwrap(), HostValue::Number(1.5));
+		assert_eq!(HostValue::try_from(Literal::String("lox".to_owned())).unwrap(), HostValue::String("lox".to_owned()));
+		assert_eq!(HostValue::try_from(Literal::Boolean(true)).unwrap(), HostValue::Boolean(true));
+		assert_eq!(HostValue::try_from(Literal::Nil).unwrap(), HostValue::Nil);
+
+		let array = Literal::Array(Rc::new(RefCell::new(vec![Literal::Number(1.0), Literal::Nil])));
+		assert_eq!(HostValue::try_from(array).unwrap(), HostValue::Array(vec![HostValue::Number(1.0), HostValue::Nil]));
+	}
+
+	#[test]
+	fn functions_have_no_host_value() {
+		let len = crate::functions::natives().into_iter().next().unwrap();
+		assert!(HostValue::try_from(Literal::Callable(Rc::new(len))).is_err());
+	}
+
+	#[test]
+	fn host_to_literal() {
+		assert!(matches!(Literal::from(HostValue::Number(2.0)), Literal::Number(x) if x == 2.0));
+		assert!(matches!(Literal::from(HostValue::String("a".to_owned())), Literal::String(ref s) if s == "a"));
+		assert!(matches!(Literal::from(HostValue::Boolean(false)), Literal::Boolean(false)));
+		assert!(matches!(Literal::from(HostValue::Nil), Literal::Nil));
+	}
+}
@@ -1,20 +1,104 @@
 use crate::syntax::Stmt;
+use crate::syntax::StmtKind;
 use crate::syntax::StmtVisitor;
 use crate::syntax::ExprVisitor;
 use crate::tokens::Literal;
+use crate::tokens::MapKey;
 use crate::syntax::Expr;
+use crate::syntax::FunctionDecl;
 use crate::tokens::Token;
 use crate::tokens::TokenType;
 use crate::environment::Stack;
+use crate::environment::Environment;
+use crate::symbols::Symbol;
+use crate::classes::Instance;
+use crate::classes::LoxClass;
+use crate::functions;
 use crate::functions::Callable;
 use crate::functions::LoxCalls;
+use crate::functions::LoxFunction;
+use crate::host::HostFunction;
+use crate::context;
+use crate::parser;
+use crate::parser::Parser;
+use crate::tokens::Scanner;
+use crate::ErrorReporter;
+use crate::Severity;
 
-pub struct Interpreter {
-	env: Stack
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+pub struct Interpreter<'a> {
+	env: Stack,
+	out: Box<dyn Write + 'a>,
+	// Where eprint writes, stderr unless replaced.
+	err_out: Box<dyn Write + 'a>,
+	// None reads from stdin.
+	input: Option<Box<dyn BufRead + 'a>>,
+	rng: u64,
+	depth: usize,
+	max_depth: usize,
+	// Where the outermost call's frame sat on the native stack, and how far
+	// past it calls may reach.
+	stack_base: usize,
+	stack_budget: usize,
+	// Whether `+` refuses to mix a number with a string.
+	strict_plus: bool,
+	// Whether arithmetic yielding an infinity or NaN fails.
+	finite_arithmetic: bool,
+	// Imports resolve relative to this, the directory of the file running.
+	script_dir: PathBuf,
+	// Files already imported, so each runs once and cycles end.
+	imported: HashSet<PathBuf>
+}
+
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// How much native stack calls may use on a thread that hasn't said how big
+// its stack is. Threads Rust spawns get 2MiB unless asked for more.
+const DEFAULT_STACK_BUDGET: usize = 1 << 20;
+
+thread_local! {
+	static STACK_BUDGET: Cell<usize> = const { Cell::new(DEFAULT_STACK_BUDGET) };
+}
+
+// Lets calls made on this thread use up to this many bytes of native stack,
+// which should leave room below the end of the thread's stack.
+pub fn set_stack_budget(bytes: usize) {
+	STACK_BUDGET.with(|budget| budget.set(bytes));
 }
 
+pub fn stack_budget() -> usize {
+	STACK_BUDGET.with(Cell::get)
+}
+
+// The longest string repeating one may build, in bytes, so a huge count is
+// an error rather than an allocation that takes the process down.
+pub const MAX_STRING_LEN: usize = 1 << 28;
+
+// Random numbers start from a fixed seed, so a script that never calls seed()
+// still runs the same way every time.
+const DEFAULT_SEED: u64 = 0x5eed;
+
+#[derive(Debug)]
 pub struct InterpreterError {
-	msg: String
+	msg: String,
+	reason: String,
+	lexeme: String,
+	line: usize,
+	column: usize
 }
 
 impl InterpreterError {
@@ -22,36 +106,121 @@ impl InterpreterError {
 		&self.msg
 	}
 
-	pub fn new(tk: &Token, err: &str) -> InterpreterError {
-		InterpreterError {
-			msg: format!("Error: {}, at: '{}' on line {}", err,tk.get_lexeme(), tk.get_line())
-		}
+	pub fn get_reason(&self) -> &str {
+		&self.reason
 	}
 
-	pub fn from(line: usize, lexeme: &str, msg: &str) -> InterpreterError {
+	pub fn get_lexeme(&self) -> &str {
+		&self.lexeme
+	}
+
+	pub fn get_line(&self) -> usize {
+		self.line
+	}
+
+	pub fn get_column(&self) -> usize {
+		self.column
+	}
+
+	pub fn new(tk: &Token, err: &str) -> InterpreterError {
 		InterpreterError {
-			msg: format!("Error: {}, at: '{}' on line {}",msg, lexeme, line)
+			msg: format!("Error: {}, at: '{}' on line {}", err,tk.get_lexeme(), tk.get_line()),
+			reason: err.to_owned(),
+			lexeme: tk.get_lexeme().to_owned(),
+			line: tk.get_line(),
+			column: tk.get_column()
 		}
 	}
 }
 
+#[derive(Debug)]
 pub enum RuntimeError {
-	BreakSentinel,
+	// Carry the label they target, if any.
+	BreakSentinel(Option<String>),
+	ContinueSentinel(Option<String>),
+	ReturnSentinel(Literal),
 	InterpreterError(InterpreterError)
 }
 
 impl RuntimeError {
 	pub fn get_msg(&self) -> &str {
 		match self {
-			RuntimeError::BreakSentinel => "Break ran without encapsulating loop. Report this bug in the interpreter.",
+			RuntimeError::BreakSentinel(_) => "Break ran without encapsulating loop. Report this bug in the interpreter.",
+			RuntimeError::ContinueSentinel(_) => "Continue ran without encapsulating loop. Report this bug in the interpreter.",
+			RuntimeError::ReturnSentinel(_) => "Return ran without encapsulating function. Report this bug in the interpreter.",
 			RuntimeError::InterpreterError(ie) => ie.get_msg()
 		}
 	}
 }
 
+impl fmt::Display for InterpreterError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.msg)
+	}
+}
+
+impl std::error::Error for InterpreterError {}
+
+impl fmt::Display for RuntimeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.get_msg())
+	}
+}
+
+impl std::error::Error for RuntimeError {}
+
 pub type Result<T> = std::result::Result<T,RuntimeError>;
 
-impl Interpreter {
+impl Default for Interpreter<'_> {
+	fn default() -> Self {
+		Interpreter::new()
+	}
+}
+
+impl<'a> Interpreter<'a> {
+	pub fn new() -> Interpreter<'a> {
+		Interpreter::with_output(Box::new(io::stdout()))
+	}
+
+	pub fn with_output(out: Box<dyn Write + 'a>) -> Interpreter<'a> {
+		Interpreter::build(out, None)
+	}
+
+	pub fn with_io(out: Box<dyn Write + 'a>, input: Box<dyn BufRead + 'a>) -> Interpreter<'a> {
+		Interpreter::build(out, Some(input))
+	}
+
+	// Makes the host function callable as a global, replacing anything of
+	// the same name.
+	pub fn register_host(&mut self, name: &str, func: Box<dyn HostFunction>) {
+		let host = Callable::Host(name.to_owned(), func);
+		self.env.define_global(Symbol::intern(name), Some(Literal::Callable(Rc::new(host))));
+	}
+
+	fn build(out: Box<dyn Write + 'a>, input: Option<Box<dyn BufRead + 'a>>) -> Interpreter<'a> {
+		let mut env = Stack::new();
+
+		for native in functions::natives() {
+			env.define(Symbol::intern(native.name()), Some(Literal::Callable(Rc::new(native))));
+		}
+
+		Interpreter {
+			env,
+			out,
+			err_out: Box::new(io::stderr()),
+			input,
+			rng: DEFAULT_SEED,
+			depth: 0,
+			max_depth: DEFAULT_MAX_DEPTH,
+			stack_base: 0,
+			stack_budget: DEFAULT_STACK_BUDGET,
+			strict_plus: false,
+			finite_arithmetic: false,
+			script_dir: PathBuf::new(),
+			imported: HashSet::new()
+		}
+	}
+
 	fn evaluate(&mut self, expr: &Expr) -> Result<Literal> {
 		expr.accept(self)
 	}
@@ -59,26 +228,296 @@ impl Interpreter {
 	fn execute(&mut self, stmt: &Stmt) -> Result<()> {
 		stmt.accept(self)
 	}
+
+	pub fn write_output(&mut self, text: &str) {
+		self.out.write_all(text.as_bytes()).expect("failed printing to output");
+	}
+
+	pub fn flush_output(&mut self) {
+		self.out.flush().expect("failed flushing output");
+	}
+
+	// Output is flushed first so the two streams interleave in the order the
+	// script wrote them.
+	pub fn write_error_output(&mut self, text: &str) {
+		self.flush_output();
+		self.err_out.write_all(text.as_bytes()).expect("failed printing to error output");
+		self.err_out.flush().expect("failed flushing error output");
+	}
+
+	pub fn set_error_output(&mut self, err_out: Box<dyn Write + 'a>) {
+		self.err_out = err_out;
+	}
+
+	// Reads a line without its line ending. None at the end of input, or if
+	// the input can't be read.
+	pub fn read_input_line(&mut self) -> Option<String> {
+		let mut line = String::new();
+		let read = match &mut self.input {
+			Some(input) => input.read_line(&mut line),
+			None => io::stdin().read_line(&mut line),
+		};
+
+		match read {
+			Ok(0) | Err(_) => None,
+			Ok(_) => {
+				let len = line.trim_end_matches(&['\n', '\r'][..]).len();
+				line.truncate(len);
+				Some(line)
+			}
+		}
+	}
+
+	// Calls nested deeper than this fail with a runtime error instead of
+	// overflowing the native stack.
+	pub fn set_max_depth(&mut self, max_depth: usize) {
+		self.max_depth = max_depth;
+	}
+
+	// In strict mode `+` only adds two numbers or joins two strings, as in
+	// the book, rather than turning a lone number into a string.
+	pub fn set_strict_plus(&mut self, strict_plus: bool) {
+		self.strict_plus = strict_plus;
+	}
+
+	pub fn strict_plus(&self) -> bool {
+		self.strict_plus
+	}
+
+	// By default arithmetic follows IEEE 754, so `1 / 0` aside, overflowing
+	// gives inf or -inf and undefined results give NaN. These print as `inf`,
+	// `-inf` and `NaN`; infinities equal themselves but NaN equals nothing.
+	// With finite arithmetic those results fail instead.
+	pub fn set_finite_arithmetic(&mut self, finite_arithmetic: bool) {
+		self.finite_arithmetic = finite_arithmetic;
+	}
+
+	// Applies a binary operator under the interpreter's rules.
+	fn binary(&self, left: Literal, op: &Token, right: Literal) -> Result<Literal> {
+		if self.strict_plus && op.get_type() == &TokenType::Plus && !matches!((&left, &right),
+			(Literal::Number(_), Literal::Number(_)) | (Literal::String(_), Literal::String(_))) {
+			return Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Operands must be two numbers or two strings")));
+		}
+
+		let operands = match (&left, &right) {
+			(Literal::Number(l), Literal::Number(r)) => Some((*l, *r)),
+			_ => None
+		};
+		match (binary(left, op, right)?, operands) {
+			(Literal::Number(x), Some((l, r))) if self.finite_arithmetic && !x.is_finite() => {
+				let msg = format!("{} {} {} is not a finite number", Literal::Number(l), op, Literal::Number(r));
+				Err(RuntimeError::InterpreterError(InterpreterError::new(op, &msg)))
+			},
+			(res, _) => Ok(res)
+		}
+	}
+
+	// Names the script being run, so its imports resolve next to it and it
+	// can't import itself.
+	pub fn set_script_path(&mut self, path: &Path) {
+		self.script_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+		self.imported.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+	}
+
+	// Names defined in the global scope so far, natives included.
+	pub fn global_names(&self) -> HashSet<String> {
+		self.env.global_names()
+	}
+
+	pub fn seed_random(&mut self, seed: u64) {
+		self.rng = seed;
+	}
+
+	// splitmix64, which is small and copes with any seed, including 0.
+	pub fn next_random(&mut self) -> u64 {
+		self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.rng;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		z ^ (z >> 31)
+	}
+
+	// Runs a function body in a new scope inside its closure, yielding the
+	// returned value or nil.
+	pub fn call_function(&mut self, decl: &FunctionDecl, closure: Rc<RefCell<Environment>>, args: Vec<Literal>) -> Result<Literal> {
+		let previous = self.env.push_closure(closure);
+		let mut scope = ScopeGuard::new(self, previous);
+
+		for (param, arg) in decl.params.iter().zip(args) {
+			scope.env.define(param.symbol(), Some(arg));
+		}
+		scope.hoist_functions(&decl.body);
+
+		for st in decl.body.iter() {
+			if let Err(err) = scope.execute(st) {
+				return match err {
+					RuntimeError::ReturnSentinel(value) => Ok(value),
+					err => Err(err),
+				};
+			}
+		}
+
+		Ok(Literal::Nil)
+	}
+
+	// Scans, parses and checks an imported file. Its first error is reported
+	// at the import.
+	fn load_import(&self, keyword: &Token, path: &Path) -> Result<Vec<Stmt>> {
+		let fail = |msg: String| RuntimeError::InterpreterError(InterpreterError::new(keyword, &msg));
+		let src = fs::read_to_string(path).map_err(|err| fail(format!("Could not read '{}': {}", path.display(), err)))?;
+
+		let mut err_hand = ErrorReporter::with_source(&src);
+		let tokens = Scanner::new(src, &mut err_hand).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_hand).parse();
+		if let Ok(stmts) = &stmts {
+			for err in context::check(stmts, false, self.global_names()) {
+				err.report(&mut err_hand);
+			}
+		}
+
+		let first_error = err_hand.diagnostics().iter().find(|d| d.severity == Severity::Error);
+		match (stmts, first_error) {
+			(Ok(stmts), None) => Ok(stmts),
+			(_, Some(d)) => Err(fail(format!("Error in '{}' on line {}: {}", path.display(), d.line, d.message))),
+			(Err(_), None) => Err(fail(format!("Could not parse '{}'", path.display()))),
+		}
+	}
+
+	// Runs the body of a loop once, false when a break ends the loop. Breaks
+	// and continues naming another label pass through to the loop they name.
+	fn loop_body(&mut self, label: &Option<Token>, body: &Stmt) -> Result<bool> {
+		let targets_this = |target: &Option<String>| match target {
+			None => true,
+			Some(target) => label.as_ref().is_some_and(|l| l.get_lexeme() == target),
+		};
+
+		match self.execute(body) {
+			Err(RuntimeError::BreakSentinel(ref target)) if targets_this(target) => Ok(false),
+			Err(RuntimeError::ContinueSentinel(ref target)) if targets_this(target) => Ok(true),
+			res => res.map(|_| true),
+		}
+	}
+
+	fn define_function(&mut self, decl: &Rc<FunctionDecl>) {
+		let func = LoxFunction::new(decl.clone(), self.env.capture(), false);
+		self.env.define(decl.name.symbol(), Some(Literal::Callable(Rc::new(Callable::Function(func)))));
+	}
+
+	// Each call recurses on the native stack, by how much depends on the
+	// build and how deeply the call sits in statements and expressions, so
+	// the depth alone can't keep a call from overflowing it. Usage is
+	// measured from the outermost call.
+	fn stack_exhausted(&mut self) -> bool {
+		let marker = 0u8;
+		let here = std::ptr::addr_of!(marker) as usize;
+
+		if self.depth == 0 {
+			self.stack_base = here;
+			self.stack_budget = stack_budget();
+			return false;
+		}
+
+		self.stack_base.abs_diff(here) > self.stack_budget
+	}
+
+	// Defines the functions declared directly among the statements before
+	// any of them run, so a function can be called above its declaration.
+	// Each declaration defines its function again when reached.
+	fn hoist_functions(&mut self, stmts: &[Stmt]) {
+		for stmt in stmts {
+			if let StmtKind::Function(decl) = &stmt.kind {
+				self.define_function(decl);
+			}
+		}
+	}
+
+	fn execute_block(&mut self, stmts: &[Stmt]) -> Result<()> {
+		let previous = self.env.push_new();
+		let mut scope = ScopeGuard::new(self, previous);
+		scope.hoist_functions(stmts);
+
+		for st in stmts {
+			scope.execute(st)?;
+		}
+
+		Ok(())
+	}
+}
+
+// Puts back the scope that was current before one was entered when dropped,
+// so the scope is left however the code run in it exits: normally, by an
+// error, or by a break or return unwinding through it.
+struct ScopeGuard<'i, 'a> {
+	interpreter: &'i mut Interpreter<'a>,
+	previous: Rc<RefCell<Environment>>
+}
+
+impl<'i, 'a> ScopeGuard<'i, 'a> {
+	fn new(interpreter: &'i mut Interpreter<'a>, previous: Rc<RefCell<Environment>>) -> ScopeGuard<'i, 'a> {
+		ScopeGuard {
+			interpreter,
+			previous
+		}
+	}
 }
 
-impl StmtVisitor<Result<()>> for &mut Interpreter {
+impl<'a> Deref for ScopeGuard<'_, 'a> {
+	type Target = Interpreter<'a>;
+
+	fn deref(&self) -> &Interpreter<'a> {
+		self.interpreter
+	}
+}
+
+impl<'a> DerefMut for ScopeGuard<'_, 'a> {
+	fn deref_mut(&mut self) -> &mut Interpreter<'a> {
+		self.interpreter
+	}
+}
+
+impl Drop for ScopeGuard<'_, '_> {
+	fn drop(&mut self) {
+		self.interpreter.env.restore(self.previous.clone());
+	}
+}
+
+impl StmtVisitor<Result<()>> for &mut Interpreter<'_> {
 	fn visit_print(self, expr: &Expr) -> Result<()> {
 		let val = self.evaluate(expr)?;
-		println!("{}", val.to_string());
+		self.write_output(&format!("{}\n", val));
 		Ok(())
 	}
 
-	fn visit_break(self, _line: usize) -> Result<()> {
-		Err(RuntimeError::BreakSentinel)
+	fn visit_break(self, _line: usize, label: &Option<Token>) -> Result<()> {
+		Err(RuntimeError::BreakSentinel(label.as_ref().map(|l| l.get_lexeme().to_owned())))
+	}
+
+	fn visit_continue(self, _line: usize, label: &Option<Token>) -> Result<()> {
+		Err(RuntimeError::ContinueSentinel(label.as_ref().map(|l| l.get_lexeme().to_owned())))
+	}
+
+	fn visit_empty(self, _line: usize) -> Result<()> {
+		Ok(())
 	}
 
-	fn visit_while(self,cond: &Expr, then: &Stmt) -> Result<()> {
+	fn visit_while(self, label: &Option<Token>, cond: &Expr, then: &Stmt, increment: &Option<Box<Expr>>) -> Result<()> {
 		while is_truthy(&(self.evaluate(cond)?)) {
-			let res = self.execute(then);
-			if let Err(RuntimeError::BreakSentinel) =  res {
+			if !self.loop_body(label, then)? {
+				break;
+			}
+
+			if let Some(increment) = increment {
+				self.evaluate(increment)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn visit_do_while(self, label: &Option<Token>, body: &Stmt, cond: &Expr) -> Result<()> {
+		while self.loop_body(label, body)? {
+			if !is_truthy(&(self.evaluate(cond)?)) {
 				break;
-			} else {
-				res?;
 			}
 		}
 
@@ -99,21 +538,32 @@ impl StmtVisitor<Result<()>> for &mut Interpreter {
 		}
 	}
 
-	fn visit_block_stmt(self, stmts: &Vec<Stmt>) -> Result<()> {
-		self.env.push_new();
+	fn visit_block_stmt(self, stmts: &[Stmt]) -> Result<()> {
+		self.execute_block(stmts)
+	}
 
-		for st in stmts {
-			let res = self.execute(st);
+	// Cases don't fall through: only the body of the first matching case (or
+	// the default) runs, each in its own scope.
+	fn visit_switch(self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) -> Result<()> {
+		let subject = self.evaluate(subject)?;
+		let mut body = default.as_ref();
+
+		for (value, stmts) in cases {
+			if subject == self.evaluate(value)? {
+				body = Some(stmts);
+				break;
+			}
+		}
 
-			if let Err(_) = res {
-				self.env.restore_old();
-				return res;
+		if let Some(stmts) = body {
+			let res = self.execute_block(stmts);
+			if let Err(RuntimeError::BreakSentinel(None)) = res {
+				return Ok(());
 			}
+			res?;
 		}
-		
-		self.env.restore_old();
+
 		Ok(())
-		
 	}
 
 	fn visit_variable(self, name: &Token, init: &Option<Expr>) -> Result<()> {
@@ -123,7 +573,18 @@ impl StmtVisitor<Result<()>> for &mut Interpreter {
 			None
 		};
 
-		self.env.define(name.get_lexeme().to_owned(), value);
+		self.env.define(name.symbol(), value);
+		Ok(())
+	}
+
+	fn visit_global(self, name: &Token, init: &Option<Expr>) -> Result<()> {
+		let value = if let Some(init) = init {
+			Some(self.evaluate(init)?)
+		} else {
+			None
+		};
+
+		self.env.define_global(name.symbol(), value);
 		Ok(())
 	}
 
@@ -132,33 +593,252 @@ impl StmtVisitor<Result<()>> for &mut Interpreter {
 		Ok(())
 	}
 
+	fn visit_function(self, decl: &Rc<FunctionDecl>) -> Result<()> {
+		self.define_function(decl);
+		Ok(())
+	}
+
+	fn visit_return(self, _keyword: &Token, value: &Option<Expr>) -> Result<()> {
+		let value = match value {
+			Some(value) => self.evaluate(value)?,
+			None => Literal::Nil,
+		};
+
+		Err(RuntimeError::ReturnSentinel(value))
+	}
+
+	// Methods of a subclass close over a scope binding `super`.
+	fn visit_class(self, name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) -> Result<()> {
+		let mut previous = None;
+		let superclass = match superclass {
+			Some(expr) => match self.evaluate(expr)? {
+				Literal::Callable(func) => match &*func {
+					Callable::Class(class) => {
+						previous = Some(self.env.push_new());
+						self.env.define(Symbol::intern("super"), Some(Literal::Callable(func.clone())));
+						Some(class.clone())
+					},
+					_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(name, "Superclass must be a class"))),
+				},
+				_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(name, "Superclass must be a class"))),
+			},
+			None => None,
+		};
+
+		let methods = methods.iter().map(|decl| {
+			let is_initializer = decl.name.get_lexeme() == "init";
+			(decl.name.get_lexeme().to_owned(), LoxFunction::new(decl.clone(), self.env.capture(), is_initializer))
+		}).collect();
+
+		if let Some(previous) = previous {
+			self.env.restore(previous);
+		}
+
+		let class = LoxClass::new(name.get_lexeme().to_owned(), superclass, methods);
+		self.env.define(name.symbol(), Some(Literal::Callable(Rc::new(Callable::Class(Rc::new(class))))));
+		Ok(())
+	}
+
+	// Runs the file's top level in the global scope, wherever the import
+	// sits. A file already imported is skipped.
+	fn visit_import(self, keyword: &Token, path: &str) -> Result<()> {
+		let path = self.script_dir.join(path);
+		let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+		if self.imported.contains(&canonical) {
+			return Ok(());
+		}
+
+		let stmts = self.load_import(keyword, &path)?;
+		self.imported.insert(canonical);
+
+		let script_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+		let script_dir = std::mem::replace(&mut self.script_dir, script_dir);
+		let previous = self.env.enter_globals();
+		let res = {
+			let mut scope = ScopeGuard::new(&mut *self, previous);
+			scope.hoist_functions(&stmts);
+			stmts.iter().try_for_each(|st| scope.execute(st))
+		};
+
+		self.script_dir = script_dir;
+		res
+	}
+
 }
 
 
-impl ExprVisitor<Result<Literal>> for &mut Interpreter {
+impl ExprVisitor<Result<Literal>> for &mut Interpreter<'_> {
 
-	fn visit_call(self, callee: &Expr, tk: &Token, args: &Vec<Expr>) -> Result<Literal> {
+	fn visit_call(self, callee: &Expr, tk: &Token, args: &[Expr]) -> Result<Literal> {
 		let callee = self.evaluate(callee)?;
 
-		let args = args.into_iter().map(|x|self.evaluate(x)).collect::<Result<Vec<_>>>();
+		let args = args.iter().map(|x|self.evaluate(x)).collect::<Result<Vec<_>>>()?;
+
+		let func = Callable::from(callee, tk)?;
+		if !func.arity().accepts(args.len()) {
+			let msg = format!("Expected {} arguments but got {}", func.arity(), args.len());
+			return Err(RuntimeError::InterpreterError(InterpreterError::new(tk, &msg)));
+		}
+
+		if self.depth >= self.max_depth || self.stack_exhausted() {
+			return Err(RuntimeError::InterpreterError(InterpreterError::new(tk, "Stack overflow: maximum call depth exceeded")));
+		}
+
+		self.depth += 1;
+		let res = func.call(&mut *self, tk, args);
+		self.depth -= 1;
+		res
+	}
+
+	fn visit_array(self, elements: &[Expr]) -> Result<Literal> {
+		let items = elements.iter().map(|x| self.evaluate(x)).collect::<Result<Vec<_>>>()?;
+		Ok(Literal::Array(Rc::new(RefCell::new(items))))
+	}
+
+	fn visit_index(self, object: &Expr, bracket: &Token, index: &Expr) -> Result<Literal> {
+		let object = self.evaluate(object)?;
+		let index = self.evaluate(index)?;
+
+		let item = match &object {
+			Literal::String(s) => s.chars().nth(unpack_index(index, bracket)?).map(|c| Literal::String(c.to_string())),
+			Literal::Array(items) => items.borrow().get(unpack_index(index, bracket)?).cloned(),
+			Literal::Map(entries) => {
+				let key = unpack_key(&index, bracket)?;
+				return entries.borrow().get(&key).cloned()
+					.ok_or_else(|| RuntimeError::InterpreterError(InterpreterError::new(bracket, "Key not found")));
+			},
+			_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(bracket, "Only strings, arrays and maps can be indexed"))),
+		};
+
+		item.ok_or_else(|| RuntimeError::InterpreterError(InterpreterError::new(bracket, "Index out of range")))
+	}
+
+	fn visit_set_index(self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> Result<Literal> {
+		let object = self.evaluate(object)?;
+		let index = self.evaluate(index)?;
+		let value = self.evaluate(value)?;
+
+		match &object {
+			Literal::Array(items) => {
+				let index = unpack_index(index, bracket)?;
+				let mut items = items.borrow_mut();
+				match items.get_mut(index) {
+					Some(item) => *item = value.clone(),
+					None => return Err(RuntimeError::InterpreterError(InterpreterError::new(bracket, "Index out of range"))),
+				}
+			},
+			Literal::Map(entries) => {
+				let key = unpack_key(&index, bracket)?;
+				entries.borrow_mut().insert(key, value.clone());
+			},
+			_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(bracket, "Only arrays and maps can be assigned into"))),
+		}
+
+		Ok(value)
+	}
+
+	fn visit_get(self, object: &Expr, name: &Token) -> Result<Literal> {
+		match self.evaluate(object)? {
+			Literal::Instance(instance) => Instance::get(&instance, name),
+			_ => Err(RuntimeError::InterpreterError(InterpreterError::new(name, "Only instances have properties"))),
+		}
+	}
+
+	fn visit_set(self, object: &Expr, name: &Token, value: &Expr) -> Result<Literal> {
+		let instance = match self.evaluate(object)? {
+			Literal::Instance(instance) => instance,
+			_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(name, "Only instances have fields"))),
+		};
+
+		let value = self.evaluate(value)?;
+		instance.borrow_mut().set(name, value.clone());
+		Ok(value)
+	}
+
+	fn visit_this(self, keyword: &Token) -> Result<Literal> {
+		self.env.get(keyword)
+	}
+
+	// `this` is bound in the scope just inside the one holding `super`, so
+	// both are visible from the method body.
+	fn visit_super(self, keyword: &Token, method: &Token) -> Result<Literal> {
+		let superclass = match self.env.get(keyword)? {
+			Literal::Callable(func) => match &*func {
+				Callable::Class(class) => class.clone(),
+				_ => unreachable!(),
+			},
+			_ => unreachable!(),
+		};
+
+		let this = Token::new(TokenType::This, "this".to_owned(), keyword.get_line(), keyword.get_column());
+		let instance = self.env.get(&this)?;
 
-		let func = Callable::from(callee)?;
-		func.call(self, args)
+		match superclass.find_method(method.get_lexeme()) {
+			Some(found) => Ok(Literal::Callable(Rc::new(Callable::Function(found.bind(instance))))),
+			None => Err(RuntimeError::InterpreterError(InterpreterError::new(method, "Undefined property"))),
+		}
+	}
+
+	fn visit_map(self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<Literal> {
+		let mut map = HashMap::new();
+
+		for (key, value) in entries.iter() {
+			let key = unpack_key(&self.evaluate(key)?, brace)?;
+			map.insert(key, self.evaluate(value)?);
+		}
+
+		Ok(Literal::Map(Rc::new(RefCell::new(map))))
+	}
+
+	// Slices take char offsets for strings. A missing start or end bound
+	// defaults to the start or end of the value.
+	fn visit_slice(self, object: &Expr, bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) -> Result<Literal> {
+		let object = self.evaluate(object)?;
+		let len = match &object {
+			Literal::String(s) => s.chars().count(),
+			Literal::Array(items) => items.borrow().len(),
+			_ => return Err(RuntimeError::InterpreterError(InterpreterError::new(bracket, "Only strings and arrays can be sliced"))),
+		};
+
+		let start = match start {
+			Some(start) => unpack_index(self.evaluate(start)?, bracket)?,
+			None => 0,
+		};
+		let end = match end {
+			Some(end) => unpack_index(self.evaluate(end)?, bracket)?,
+			None => len,
+		};
+
+		if start > end || end > len {
+			return Err(RuntimeError::InterpreterError(InterpreterError::new(bracket, "Slice out of range")));
+		}
+
+		match object {
+			Literal::String(s) => Ok(Literal::String(s.chars().skip(start).take(end - start).collect())),
+			Literal::Array(items) => Ok(Literal::Array(Rc::new(RefCell::new(items.borrow()[start..end].to_vec())))),
+			_ => unreachable!()
+		}
 	}
 
 	fn visit_literal(self, ltrl: &Literal) -> Result<Literal> {
 		Ok(ltrl.clone())
 	}
 
+	// `and` and `or` give back the operand that decided the result rather than
+	// a boolean, and only evaluate the right side when the left doesn't decide.
 	fn visit_logical(self, left: &Expr, op: &Token, right: &Expr) -> Result<Literal> {
 		let left = self.evaluate(left)?;
 
-		if let TokenType::Or = op.get_type() {
-			if is_truthy(&left) {
+		match op.get_type() {
+			TokenType::Or => if is_truthy(&left) {
 				return Ok(left);
-			}
-		} else {
-			if !is_truthy(&left) {
+			},
+			// xor always needs both sides, so it cannot short-circuit.
+			TokenType::Xor => {
+				let right = self.evaluate(right)?;
+				return Ok(Literal::Boolean(is_truthy(&left) != is_truthy(&right)));
+			},
+			_ => if !is_truthy(&left) {
 				return Ok(left);
 			}
 		}
@@ -183,6 +863,10 @@ impl ExprVisitor<Result<Literal>> for &mut Interpreter {
 				Ok(Literal::Number(-x))
 			},
 			TokenType::Bang => Ok(Literal::Boolean(!is_truthy(&right))),
+			TokenType::Tilde => {
+				let x = unpack_integer(right, op)?;
+				Ok(Literal::Number(!x as f64))
+			},
 			_ => unreachable!()
 		}
 	}
@@ -195,6 +879,16 @@ impl ExprVisitor<Result<Literal>> for &mut Interpreter {
 	}
 
 
+	// Steps the variable the way `x += 1` would, but yields the old value.
+	fn visit_postfix(self, name: &Token, op: &Token) -> Result<Literal> {
+		let old = self.env.get(name)?;
+		let step = parser::compound_operator(op).expect("postfix operators step by one");
+		let new = self.binary(old.clone(), &step, Literal::Number(1.0))?;
+
+		self.env.assign(name, new)?;
+		Ok(old)
+	}
+
 	fn visit_ternary(self, _op: &Token, left: &Expr, middle: &Expr, right: &Expr) -> Result<Literal> {
 		let left = self.evaluate(left)?;
 		if is_truthy(&left) {
@@ -207,73 +901,100 @@ impl ExprVisitor<Result<Literal>> for &mut Interpreter {
 	fn visit_binary(self, left: &Expr, op: &Token, right: &Expr) -> Result<Literal> {
 		let left = self.evaluate(left)?;
 		let right = self.evaluate(right)?;
-
-		match op.get_type() {
-			TokenType::Minus | TokenType::Slash | TokenType::Star | TokenType::Greater |
-			TokenType::GreaterEqual | TokenType::LessEqual | TokenType::Less => {
-				let left = unpack_number(left,op)?;
-				let right = unpack_number(right,op)?;
-				match op.get_type() {
-					TokenType::Minus => Ok(Literal::Number(left - right)),
-					TokenType::Slash => if right == 0.0 {
-						Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Division by zero")))
-					} else {
-						Ok(Literal::Number(left / right))
-					},
-					TokenType::Star => Ok(Literal::Number(left * right)),
-					TokenType::Greater => Ok(Literal::Boolean(left > right)),
-					TokenType::GreaterEqual => Ok(Literal::Boolean(left >= right)),
-					TokenType::Less => Ok(Literal::Boolean(left < right)),
-					TokenType::LessEqual => Ok(Literal::Boolean(left <= right)),
-					_ => unreachable!()
-				}
-			},
-			TokenType::Plus => {
-				let nl = unpack_number(left.clone(), op);
-				let nr = unpack_number(right.clone(), op);
-				if let (Ok(nl),Ok(nr)) = (nl,nr) {
-					Ok(Literal::Number(nl+nr))
-				} else {
-					let mut sl = unpack_into_string(left, op)?;
-					let sr = unpack_into_string(right, op)?;
-					sl.push_str(&sr);
-					Ok(Literal::String(sl))
-				}
-			},
-			TokenType::EqualEqual => Ok(Literal::Boolean(is_equal(&left,&right))),
-			TokenType::BangEqual => Ok(Literal::Boolean(!is_equal(&left,&right))),
-			_ => unreachable!()
-		}
-
+		self.binary(left, op, right)
 	}
 }
 
-fn is_equal(f: &Literal, s: &Literal) -> bool {
-	match f {
-		Literal::Number(f) => if let Literal::Number(s) = s {
-			f == s
-		} else {
-			false
+// Applies a binary operator to values already evaluated.
+fn binary(left: Literal, op: &Token, right: Literal) -> Result<Literal> {
+	match op.get_type() {
+		TokenType::Star if matches!((&left, &right), (Literal::String(_), Literal::Number(_)) | (Literal::Number(_), Literal::String(_))) => {
+			repeat_string(left, right, op)
 		},
-		Literal::Boolean(f) => if let Literal::Boolean(s) = s {
-			f == s
-		} else {
-			false
+		TokenType::Minus | TokenType::Slash | TokenType::Star | TokenType::Greater |
+		TokenType::GreaterEqual | TokenType::LessEqual | TokenType::Less => {
+			let left = unpack_number(left,op)?;
+			let right = unpack_number(right,op)?;
+			match op.get_type() {
+				TokenType::Minus => Ok(Literal::Number(left - right)),
+				TokenType::Slash => if right == 0.0 {
+					Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Division by zero")))
+				} else {
+					Ok(Literal::Number(left / right))
+				},
+				TokenType::Star => Ok(Literal::Number(left * right)),
+				TokenType::Greater => Ok(Literal::Boolean(left > right)),
+				TokenType::GreaterEqual => Ok(Literal::Boolean(left >= right)),
+				TokenType::Less => Ok(Literal::Boolean(left < right)),
+				TokenType::LessEqual => Ok(Literal::Boolean(left <= right)),
+				_ => unreachable!()
+			}
 		},
-		Literal::String(f) => if let Literal::String(s) = s {
-			f == s
-		} else {
-			false
+		TokenType::Plus => {
+			let nl = unpack_number(left.clone(), op);
+			let nr = unpack_number(right.clone(), op);
+			if let (Ok(nl),Ok(nr)) = (nl,nr) {
+				Ok(Literal::Number(nl+nr))
+			} else {
+				let has_string = matches!(left, Literal::String(_)) || matches!(right, Literal::String(_));
+				let mut sl = unpack_concat_operand(left, op, has_string)?;
+				let sr = unpack_concat_operand(right, op, has_string)?;
+				sl.push_str(&sr);
+				Ok(Literal::String(sl))
+			}
+		},
+		TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::LessLess |
+		TokenType::GreaterGreater => {
+			let left = unpack_integer(left,op)?;
+			let right = unpack_integer(right,op)?;
+			let res = match op.get_type() {
+				TokenType::Ampersand => left & right,
+				TokenType::Pipe => left | right,
+				TokenType::Caret => left ^ right,
+				TokenType::LessLess | TokenType::GreaterGreater => {
+					if !(0..64).contains(&right) {
+						return Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Shift amount must be between 0 and 63")));
+					}
+
+					if let TokenType::LessLess = op.get_type() {
+						left << right
+					} else {
+						left >> right
+					}
+				},
+				_ => unreachable!()
+			};
+			Ok(Literal::Number(res as f64))
 		},
-		Literal::Nil => f==s
+		TokenType::EqualEqual => Ok(Literal::Boolean(left == right)),
+		TokenType::BangEqual => Ok(Literal::Boolean(left != right)),
+		TokenType::Comma => Ok(right),
+		_ => unreachable!()
 	}
 }
 
-fn is_truthy(ltl: &Literal) -> bool {
+// A string times a whole number, in either order, repeats the string.
+fn repeat_string(left: Literal, right: Literal, op: &Token) -> Result<Literal> {
+	let (text, count) = match (left, right) {
+		(Literal::String(text), Literal::Number(count)) | (Literal::Number(count), Literal::String(text)) => (text, count),
+		_ => unreachable!()
+	};
+
+	if count < 0.0 || count.fract() != 0.0 || !count.is_finite() {
+		return Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Strings can only be repeated a non-negative whole number of times")));
+	}
+
+	match text.len().checked_mul(count as usize) {
+		Some(len) if len <= MAX_STRING_LEN => Ok(Literal::String(text.repeat(count as usize))),
+		_ => Err(RuntimeError::InterpreterError(InterpreterError::new(op, "Repeated string is too long")))
+	}
+}
+
+pub fn is_truthy(ltl: &Literal) -> bool {
 	match ltl {
 		Literal::Nil => false,
 		Literal::Boolean(x) => *x,
-		__ => true
+		_ => true
 	}
 }
 
@@ -284,36 +1005,715 @@ fn unpack_number(ltl: Literal, tk: &Token) -> Result<f64> {
 	}
 }
 
-fn unpack_string(ltl: Literal, tk: &Token) -> Result<String> {
-	match ltl {
-		Literal::String(x) => Ok(x),
-		_ => Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Expected String"))),
+fn unpack_integer(ltl: Literal, tk: &Token) -> Result<i64> {
+	let x = unpack_number(ltl, tk)?;
+
+	if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 {
+		Ok(x as i64)
+	} else {
+		Err(RuntimeError::InterpreterError(InterpreterError::new(tk, "Expected integer")))
 	}
 }
 
+fn unpack_index(ltl: Literal, tk: &Token) -> Result<usize> {
+	let x = unpack_integer(ltl, tk)?;
+
+	if x < 0 {
+		Err(RuntimeError::InterpreterError(InterpreterError::new(tk, "Index must not be negative")))
+	} else {
+		Ok(x as usize)
+	}
+}
+
+fn unpack_key(ltl: &Literal, tk: &Token) -> Result<MapKey> {
+	MapKey::from_literal(ltl).ok_or_else(|| {
+		RuntimeError::InterpreterError(InterpreterError::new(tk, "Map keys must be strings or numbers other than NaN"))
+	})
+}
+
 fn unpack_into_string(ltl: Literal, tk: &Token) -> Result<String> {
 	match ltl {
 		Literal::String(x) => Ok(x),
-		Literal::Number(x) => Ok(x.to_string()),
+		Literal::Number(_) => Ok(ltl.to_string()),
 		_ => Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Expected value that can be a String"))),
 	}
 }
 
-fn unpack_bool(ltl: Literal, tk: &Token) -> Result<bool> {
+// Booleans and nil can be joined onto a string, but not added to each other
+// or to numbers.
+fn unpack_concat_operand(ltl: Literal, tk: &Token, has_string: bool) -> Result<String> {
 	match ltl {
-		Literal::Boolean(x) => Ok(x),
-		_ => Err(RuntimeError::InterpreterError(<InterpreterError>::new(tk, "Expected boolean"))),
+		Literal::Boolean(_) | Literal::Nil if has_string => Ok(ltl.to_string()),
+		_ => unpack_into_string(ltl, tk)
 	}
 }
 
-pub fn interpret(statements: &Vec<Stmt>) -> Result<()> {
-	let mut visit = Interpreter {
-		env: Stack::new()
-	};
-	
+pub fn interpret(interpreter: &mut Interpreter, statements: &[Stmt]) -> Result<()> {
+	interpreter.hoist_functions(statements);
 	for stmt in statements {
-		visit.execute(stmt)?;
+		interpreter.execute(stmt)?;
 	}
 
 	Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ErrorReporter;
+	use crate::parser::Parser;
+	use crate::tokens::Scanner;
+
+	fn run(src: &str) -> Interpreter<'static> {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		assert!(!err_rep.had_error);
+
+		let mut interpreter = Interpreter::new();
+		for stmt in stmts.iter() {
+			assert!(interpreter.execute(stmt).is_ok());
+		}
+		interpreter
+	}
+
+	fn run_err(src: &str) -> RuntimeError {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		assert!(!err_rep.had_error);
+
+		match interpret(&mut Interpreter::new(), &stmts) {
+			Err(err) => err,
+			Ok(_) => panic!("expected a runtime error"),
+		}
+	}
+
+	fn run_with_input(src: &str, input: &str) -> Interpreter<'static> {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		assert!(!err_rep.had_error);
+
+		let mut interpreter = Interpreter::with_io(Box::new(io::sink()), Box::new(io::Cursor::new(input.to_owned())));
+		assert!(interpret(&mut interpreter, &stmts).is_ok());
+		interpreter
+	}
+
+	fn get(interpreter: &Interpreter, name: &str) -> Literal {
+		let tk = Token::new(TokenType::Identifier, name.to_owned(), 0, 0);
+		match interpreter.env.get(&tk) {
+			Ok(val) => val,
+			Err(_) => panic!("expected {} to be defined", name),
+		}
+	}
+
+	fn get_number(interpreter: &Interpreter, name: &str) -> f64 {
+		match get(interpreter, name) {
+			Literal::Number(x) => x,
+			_ => panic!("expected {} to hold a number", name),
+		}
+	}
+
+	#[test]
+	fn variable_errors_name_the_variable() {
+		assert_eq!(run_err("var a; print a;").get_msg(), "Error: Variable 'a' used before initialization, at: 'a' on line 1");
+		assert_eq!(run_err("{ var a; fun f() { return a; } f(); }").get_msg(), "Error: Variable 'a' used before initialization, at: 'a' on line 1");
+		assert_eq!(run_err("print b;").get_msg(), "Error: Undefined variable 'b', at: 'b' on line 1");
+		assert_eq!(run_err("b = 1;").get_msg(), "Error: Undefined variable 'b', at: 'b' on line 1");
+	}
+
+	#[test]
+	fn functions_are_hoisted_within_their_scope() {
+		let src = "var a = isEven(10);
+			fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); }
+			fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); }
+			var b; { b = twice(3); fun twice(x) { return x * 2; } }
+			fun outer() { return inner(); fun inner() { return \"inner\"; } }
+			var c = outer();";
+		let interpreter = run_with_input(src, "");
+		assert_eq!(get(&interpreter, "a"), Literal::Boolean(true));
+		assert_eq!(get_number(&interpreter, "b"), 6.0);
+		assert_eq!(get(&interpreter, "c"), Literal::String("inner".to_owned()));
+		assert!(run_err("{ fun f() { } } f();").get_msg().contains("Undefined variable 'f'"));
+	}
+
+	#[test]
+	fn unwinding_leaves_the_global_scope() {
+		let interpreter = run("fun f() { { var a = 1; { var b = 2; { return a + b; } } } } var r = f(); var after = 1;");
+		assert_eq!(get_number(&interpreter, "r"), 3.0);
+		assert!(interpreter.global_names().contains("after"));
+		assert!(!interpreter.global_names().contains("a"));
+
+		let mut interpreter = Interpreter::new();
+		for (src, ok) in [("{ var a = 1; { { nil(); } } }", false), ("while (true) { { { break; } } } var after = 1;", true)].iter() {
+			let mut err_rep = ErrorReporter::new();
+			let tokens = Scanner::new(src.to_string(), &mut err_rep).scan_tokens();
+			let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+			assert_eq!(interpret(&mut interpreter, &stmts).is_ok(), *ok);
+		}
+		assert!(interpreter.global_names().contains("after"));
+		assert!(!interpreter.global_names().contains("a"));
+	}
+
+	#[test]
+	fn eprint_writes_to_the_error_output() {
+		let mut out = Vec::new();
+		let mut err_out = Vec::new();
+		{
+			let mut interpreter = Interpreter::with_output(Box::new(&mut out));
+			interpreter.set_error_output(Box::new(&mut err_out));
+
+			let mut err_rep = ErrorReporter::new();
+			let tokens = Scanner::new("print 1; eprint(\"oops\"); eprint([2]); print 3;".to_owned(), &mut err_rep).scan_tokens();
+			let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+			assert!(interpret(&mut interpreter, &stmts).is_ok());
+		}
+		assert_eq!(String::from_utf8(out).unwrap(), "1\n3\n");
+		assert_eq!(String::from_utf8(err_out).unwrap(), "oops\n[2]\n");
+	}
+
+	#[test]
+	fn increments_yield_new_or_old_values() {
+		let interpreter = run("var x = 5; var a = x++ * 10 + x; var b = ++x * 10 + x; var c = x-- + --x;
+			var s = 0; for (var i = 0; i < 3; i++) s = s + i; var f = 0.1; var g = f++;");
+		assert_eq!(get_number(&interpreter, "a"), 56.0);
+		assert_eq!(get_number(&interpreter, "b"), 77.0);
+		assert_eq!(get_number(&interpreter, "c"), 12.0);
+		assert_eq!(get_number(&interpreter, "x"), 5.0);
+		assert_eq!(get_number(&interpreter, "s"), 3.0);
+		assert_eq!(get_number(&interpreter, "g"), 0.1);
+		assert!(run_err("var t = true; t++;").get_msg().contains("Expected value that can be a String"));
+	}
+
+	#[test]
+	fn compound_assignment() {
+		let interpreter = run("var x = 10; var y = x += 5; x -= 3; x *= 2; x /= 4;");
+		assert_eq!(get_number(&interpreter, "x"), 6.0);
+		assert_eq!(get_number(&interpreter, "y"), 15.0);
+	}
+
+	#[test]
+	fn global_targets_outermost_scope() {
+		let interpreter = run("var x = 1; { var x = 2; { global x = 3; global y = 4; x = 5; } }");
+		assert_eq!(get_number(&interpreter, "x"), 3.0);
+		assert_eq!(get_number(&interpreter, "y"), 4.0);
+	}
+
+	#[test]
+	fn global_declaration_keeps_value() {
+		let interpreter = run("var x = 1; { global x; }");
+		assert_eq!(get_number(&interpreter, "x"), 1.0);
+	}
+
+	#[test]
+	fn bitwise_operators() {
+		let interpreter = run("var a = 6 & 3; var b = 1 << 4; var c = ~0; var d = 6 | 3; var e = 6 ^ 3; var f = 256 >> 4;
+			var g = 1 | 2 == 3;");
+		assert_eq!(get_number(&interpreter, "a"), 2.0);
+		assert_eq!(get_number(&interpreter, "b"), 16.0);
+		assert_eq!(get_number(&interpreter, "c"), -1.0);
+		assert_eq!(get_number(&interpreter, "d"), 7.0);
+		assert_eq!(get_number(&interpreter, "e"), 5.0);
+		assert_eq!(get_number(&interpreter, "f"), 16.0);
+		assert!(matches!(get(&interpreter, "g"), Literal::Boolean(true)));
+	}
+
+	#[test]
+	fn bitwise_operators_need_integers() {
+		assert!(run_err("1.5 & 1;").get_msg().contains("Expected integer"));
+		assert!(run_err("1 << -1;").get_msg().contains("Shift amount"));
+		assert!(run_err("1 << 64;").get_msg().contains("Shift amount"));
+	}
+
+	#[test]
+	fn len_native() {
+		let interpreter = run("var a = len(\"héllo\"); var b = len([1,2,3]); var c = len([]);");
+		assert_eq!(get_number(&interpreter, "a"), 5.0);
+		assert_eq!(get_number(&interpreter, "b"), 3.0);
+		assert_eq!(get_number(&interpreter, "c"), 0.0);
+	}
+
+	#[test]
+	fn input_reads_lines_until_eof() {
+		let interpreter = run_with_input("var a = input(); var b = input(); var c = input();", "first\r\nsecond\n");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "first"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "second"));
+		assert!(matches!(get(&interpreter, "c"), Literal::Nil));
+	}
+
+	#[test]
+	fn input_writes_its_prompt_first() {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new("var name = input(\"Name: \"); print \"Hi \" + name; var rest = read_line(\"More? \");".to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+
+		let mut out = Vec::new();
+		{
+			let mut interpreter = Interpreter::with_io(Box::new(&mut out), Box::new(io::Cursor::new("Ada\n".to_owned())));
+			assert!(interpret(&mut interpreter, &stmts).is_ok());
+			assert!(matches!(get(&interpreter, "rest"), Literal::Nil));
+		}
+		assert_eq!(String::from_utf8(out).unwrap(), "Name: Hi Ada\nMore? ");
+
+		assert!(run_err("input(1);").get_msg().contains("input() expects a string prompt"));
+		assert!(run_err("input(\"a\", \"b\");").get_msg().contains("Expected 0 to 1 arguments but got 2"));
+	}
+
+	#[test]
+	fn errors_and_values_display() {
+		let err = run_err("var a = 1;\nprint a - \"b\";");
+		assert_eq!(format!("{}", err), "Error: Expected number, at: '-' on line 2");
+		assert_eq!(err.to_string(), err.get_msg());
+
+		let boxed: Box<dyn std::error::Error> = Box::new(err);
+		assert_eq!(boxed.to_string(), "Error: Expected number, at: '-' on line 2");
+
+		let interpreter = run("var a = [1.5, \"x\", nil, {\"k\": true}];");
+		assert_eq!(format!("<{}>", get(&interpreter, "a")), "<[1.5, x, nil, {k: true}]>");
+	}
+
+	#[test]
+	fn labelled_break_and_continue() {
+		let interpreter = run("var hits = 0; var total = 0;
+			outer: for (var i = 0; i < 5; i = i + 1) {
+				var j = 0;
+				while (true) {
+					j = j + 1;
+					if (j == 2) continue;
+					if (j > 3) continue outer;
+					if (i == 3) break outer;
+					hits = hits + 1;
+				}
+			}
+			var k = 0;
+			do { k = k + 1; if (k < 3) continue; break; } while (true);
+			for (var i = 0; i < 4; i = i + 1) { if (i == 1) continue; total = total + i; }");
+		assert_eq!(get_number(&interpreter, "hits"), 6.0);
+		assert_eq!(get_number(&interpreter, "k"), 3.0);
+		assert_eq!(get_number(&interpreter, "total"), 5.0);
+	}
+
+	#[test]
+	fn logical_operators_return_the_deciding_operand() {
+		let interpreter = run("var calls = 0;
+			fun side_effect() { calls = calls + 1; return true; }
+			var a = false and side_effect();
+			var b = true or side_effect();
+			var c = nil or 5;
+			var d = 1 and 2;
+			var e = nil and side_effect();
+			var f = 0 or side_effect();");
+		assert!(matches!(get(&interpreter, "a"), Literal::Boolean(false)));
+		assert!(matches!(get(&interpreter, "b"), Literal::Boolean(true)));
+		assert_eq!(get_number(&interpreter, "c"), 5.0);
+		assert_eq!(get_number(&interpreter, "d"), 2.0);
+		assert!(matches!(get(&interpreter, "e"), Literal::Nil));
+		assert_eq!(get_number(&interpreter, "f"), 0.0);
+		assert_eq!(get_number(&interpreter, "calls"), 0.0);
+	}
+
+	#[test]
+	fn logical_operands_are_not_coerced() {
+		let interpreter = run("var a = nil or \"default\"; var b = 0 and \"x\"; var c = \"\" or 1; var d = false or nil;");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "default"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "x"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s.is_empty()));
+		assert!(matches!(get(&interpreter, "d"), Literal::Nil));
+	}
+
+	#[test]
+	fn format_fills_placeholders() {
+		let interpreter = run("var a = format(\"{} + {} = {}\", 1, 2, 3); var b = fmt(\"{{{}}} {}\", \"x\", [nil]); var c = format(\"plain\");");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "1 + 2 = 3"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "{x} [nil]"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s == "plain"));
+
+		assert!(run_err("format(\"{} {}\", 1);").get_msg().contains("format() has more placeholders than values"));
+		assert!(run_err("format(\"{}\", 1, 2);").get_msg().contains("format() has more values than placeholders"));
+		assert!(run_err("format(\"{\");").get_msg().contains("unmatched brace"));
+		assert!(run_err("format(1);").get_msg().contains("format() expects a string template"));
+		assert!(run_err("format();").get_msg().contains("Expected at least 1 arguments but got 0"));
+	}
+
+	#[test]
+	fn strings_repeat() {
+		let interpreter = run("var a = \"x\" * 0; var b = \"ab\" * 2; var c = 3 * \"-\"; var d = 2 * 3;");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s.is_empty()));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "abab"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s == "---"));
+		assert_eq!(get_number(&interpreter, "d"), 6.0);
+
+		assert!(run_err("\"x\" * -1;").get_msg().contains("Strings can only be repeated a non-negative whole number of times"));
+		assert!(run_err("\"x\" * 1.5;").get_msg().contains("non-negative whole number"));
+		assert!(run_err("\"x\" * \"y\";").get_msg().contains("Expected number"));
+		assert!(run_err("\"x\" * 10000000000000000000;").get_msg().contains("Repeated string is too long"));
+		assert!(run_err("\"ab\" * 100000000000;").get_msg().contains("Repeated string is too long"));
+		assert!(matches!(get(&run("var e = \"\" * 10000000000000000000;"), "e"), Literal::String(ref s) if s.is_empty()));
+	}
+
+	#[test]
+	fn read_line_is_input() {
+		let interpreter = run_with_input("var a = read_line(); var b = read_line(); var c = read_line();", "one\ntwo");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "one"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "two"));
+		assert!(matches!(get(&interpreter, "c"), Literal::Nil));
+	}
+
+	#[test]
+	fn number_parses_strings() {
+		let interpreter = run_with_input("var a = number(input()) + 1; var b = number(\"abc\"); var c = number(\"inf\");", " 41 \n");
+		assert_eq!(get_number(&interpreter, "a"), 42.0);
+		assert!(matches!(get(&interpreter, "b"), Literal::Nil));
+		assert!(matches!(get(&interpreter, "c"), Literal::Nil));
+		assert!(run_err("number(nil);").get_msg().contains("number() expects a string or number"));
+	}
+
+	#[test]
+	fn conversion_natives() {
+		let interpreter = run("var a = number(\"3.5\"); var b = string(42) + \"!\"; var c = string([1, nil]); var d = bool(0); var e = bool(nil);");
+		assert_eq!(get_number(&interpreter, "a"), 3.5);
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "42!"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s == "[1, nil]"));
+		assert!(matches!(get(&interpreter, "d"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "e"), Literal::Boolean(false)));
+	}
+
+	#[test]
+	fn math_natives() {
+		let interpreter = run("var a = sqrt(9); var b = floor(3.7); var c = ceil(3.2); var d = round(2.5); var e = abs(-4);
+			var f = min(2, -1); var g = max(2, -1); var h = pow(2, 10);");
+		assert_eq!(get_number(&interpreter, "a"), 3.0);
+		assert_eq!(get_number(&interpreter, "b"), 3.0);
+		assert_eq!(get_number(&interpreter, "c"), 4.0);
+		assert_eq!(get_number(&interpreter, "d"), 3.0);
+		assert_eq!(get_number(&interpreter, "e"), 4.0);
+		assert_eq!(get_number(&interpreter, "f"), -1.0);
+		assert_eq!(get_number(&interpreter, "g"), 2.0);
+		assert_eq!(get_number(&interpreter, "h"), 1024.0);
+		assert!(run_err("sqrt(\"9\");").get_msg().contains("sqrt() expects numbers"));
+		assert!(run_err("max(1, nil);").get_msg().contains("max() expects numbers"));
+	}
+
+	#[test]
+	fn nan_is_unequal_to_itself() {
+		let interpreter = run("var nan = sqrt(-1); var a = is_nan(nan); var b = is_nan(1); var c = nan == nan; var d = nan != nan;");
+		assert!(matches!(get(&interpreter, "a"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "b"), Literal::Boolean(false)));
+		assert!(matches!(get(&interpreter, "c"), Literal::Boolean(false)));
+		assert!(matches!(get(&interpreter, "d"), Literal::Boolean(true)));
+		assert!(run_err("is_nan(\"x\");").get_msg().contains("is_nan() expects numbers"));
+	}
+
+	#[test]
+	fn assert_native() {
+		run("assert(1 < 2); assert(\"\", \"unused\");");
+		assert_eq!(run_err("assert(nil, \"value was \" + nil);").get_msg(), "Error: Assertion failed: value was nil, at: ')' on line 1");
+		assert!(run_err("assert();").get_msg().contains("Expected 1 to 2 arguments but got 0"));
+
+		match run_err("assert(true);\n\nassert(1 > 2);") {
+			RuntimeError::InterpreterError(err) => {
+				assert_eq!(err.get_reason(), "Assertion failed");
+				assert_eq!(err.get_line(), 3);
+			},
+			_ => panic!("expected an interpreter error"),
+		}
+	}
+
+	#[test]
+	fn strings_concatenate_booleans_and_nil() {
+		let interpreter = run("var a = \"v\" + true; var b = \"v\" + nil; var c = false + \"v\"; var d = 1 + 2 == 3; var e = \"n\" + 1;");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "vtrue"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "vnil"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s == "falsev"));
+		assert!(matches!(get(&interpreter, "d"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "e"), Literal::String(ref s) if s == "n1"));
+		assert!(run_err("true + nil;").get_msg().contains("Expected value that can be a String"));
+		assert!(run_err("1 + nil;").get_msg().contains("Expected value that can be a String"));
+	}
+
+	#[test]
+	fn seeded_random_repeats() {
+		let src = "seed(1); var a = random(); var b = random(); var c = randint(1, 6); var d = randint(-3, -3);";
+		let first = run(src);
+		let second = run(src);
+
+		for name in ["a", "b", "c"].iter() {
+			assert_eq!(get_number(&first, name), get_number(&second, name));
+		}
+		assert!((0.0..1.0).contains(&get_number(&first, "a")));
+		assert_ne!(get_number(&first, "a"), get_number(&first, "b"));
+		assert!((1.0..=6.0).contains(&get_number(&first, "c")));
+		assert_eq!(get_number(&first, "d"), -3.0);
+
+		let unseeded = "var a = rand(); var b = rand_int(1, 100);";
+		let first = run(unseeded);
+		let second = run(unseeded);
+		assert_eq!(get_number(&first, "a"), get_number(&second, "a"));
+		assert_eq!(get_number(&first, "b"), get_number(&second, "b"));
+
+		assert!(run_err("randint(2, 1);").get_msg().contains("randint() needs lo <= hi"));
+		assert!(run_err("seed(1.5);").get_msg().contains("seed() expects integers"));
+	}
+
+	#[test]
+	fn typeof_names_each_type() {
+		let interpreter = run("fun f() {} class A {}
+			var types = [typeof(1), typeof(\"s\"), typeof(true), typeof(nil), typeof([]), typeof({\"k\": 1}),
+				typeof(f), typeof(len), typeof(A), typeof(A())];");
+		match get(&interpreter, "types") {
+			Literal::Array(items) => {
+				let names: Vec<String> = items.borrow().iter().map(|x| x.to_string()).collect();
+				assert_eq!(names, vec!["number", "string", "boolean", "nil", "array", "map", "function", "function", "class", "instance"]);
+			},
+			_ => panic!("expected an array"),
+		}
+
+		let interpreter = run("var a = type(1) == typeof(1); var b = type(type);");
+		assert!(matches!(get(&interpreter, "a"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "function"));
+	}
+
+	#[test]
+	fn strict_conversion_natives() {
+		let interpreter = run("var a = str(42) == \"42\"; var b = num(\"2.75\"); var c = num(7);");
+		assert!(matches!(get(&interpreter, "a"), Literal::Boolean(true)));
+		assert_eq!(get_number(&interpreter, "b"), 2.75);
+		assert_eq!(get_number(&interpreter, "c"), 7.0);
+		assert!(run_err("num(\"abc\");").get_msg().contains("Cannot convert to number"));
+	}
+
+	#[test]
+	fn len_rejects_other_values() {
+		assert!(run_err("len(1);").get_msg().contains("len() expects a string, array or map"));
+		assert!(run_err("len(nil);").get_msg().contains("len() expects a string, array or map"));
+		assert!(run_err("len();").get_msg().contains("Expected 1 arguments but got 0"));
+	}
+
+	#[test]
+	fn switch_runs_matching_case_only() {
+		let interpreter = run("var a = 0; var b = 0;
+			switch (1 + 1) { case 1: a = 1; case 2: a = 2; b = 1; case 3: a = 3; default: a = 4; }
+			var c = 0;
+			switch (\"x\") { case \"y\": c = 1; default: c = 2; }");
+		assert_eq!(get_number(&interpreter, "a"), 2.0);
+		assert_eq!(get_number(&interpreter, "b"), 1.0);
+		assert_eq!(get_number(&interpreter, "c"), 2.0);
+	}
+
+	#[test]
+	fn break_leaves_switch() {
+		let interpreter = run("var a = 0; var i = 0;
+			while (i < 3) { i = i + 1; switch (i) { case 2: break; a = 100; default: a = a + 1; } }");
+		assert_eq!(get_number(&interpreter, "a"), 2.0);
+		assert_eq!(get_number(&interpreter, "i"), 3.0);
+	}
+
+	fn get_string(interpreter: &Interpreter, name: &str) -> String {
+		match get(interpreter, name) {
+			Literal::String(s) => s,
+			_ => panic!("expected {} to hold a string", name),
+		}
+	}
+
+	#[test]
+	fn string_indexing() {
+		let interpreter = run("var a = \"hello\"[0]; var b = \"héllo\"[1]; var c = [1, 2, 3][2];");
+		assert_eq!(get_string(&interpreter, "a"), "h");
+		assert_eq!(get_string(&interpreter, "b"), "é");
+		assert_eq!(get_number(&interpreter, "c"), 3.0);
+	}
+
+	#[test]
+	fn string_index_errors() {
+		assert!(run_err("\"hello\"[5];").get_msg().contains("Index out of range"));
+		assert!(run_err("\"hello\"[-1];").get_msg().contains("Index must not be negative"));
+		assert!(run_err("\"hello\"[0.5];").get_msg().contains("Expected integer"));
+		assert!(run_err("1[0];").get_msg().contains("Only strings, arrays and maps can be indexed"));
+	}
+
+	#[test]
+	fn string_slicing() {
+		let interpreter = run("var s = \"hello\"; var a = s[1:3]; var b = s[:2]; var c = s[3:]; var d = s[2:2];");
+		assert_eq!(get_string(&interpreter, "a"), "el");
+		assert_eq!(get_string(&interpreter, "b"), "he");
+		assert_eq!(get_string(&interpreter, "c"), "lo");
+		assert_eq!(get_string(&interpreter, "d"), "");
+		assert!(run_err("\"hello\"[3:1];").get_msg().contains("Slice out of range"));
+		assert!(run_err("\"hello\"[0:6];").get_msg().contains("Slice out of range"));
+	}
+
+	#[test]
+	fn do_while_runs_body_once() {
+		let interpreter = run("var a = 0; do a = a + 1; while (false);
+			var b = 0; do { b = b + 1; } while (b < 5);
+			var c = 0; do { c = c + 1; if (c == 3) break; } while (true);");
+		assert_eq!(get_number(&interpreter, "a"), 1.0);
+		assert_eq!(get_number(&interpreter, "b"), 5.0);
+		assert_eq!(get_number(&interpreter, "c"), 3.0);
+	}
+
+	#[test]
+	fn xor_operator() {
+		let interpreter = run("var a = true xor true; var b = true xor nil; var c = 0 xor false; var d = nil xor false;");
+		assert!(matches!(get(&interpreter, "a"), Literal::Boolean(false)));
+		assert!(matches!(get(&interpreter, "b"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "c"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "d"), Literal::Boolean(false)));
+	}
+
+	#[test]
+	fn xor_binds_between_or_and_and() {
+		let interpreter = run("var a = 0; var b = true or false xor (a = 1); var c = true xor true and false; var d = false xor (a = a + 1);");
+		assert!(matches!(get(&interpreter, "b"), Literal::Boolean(true)));
+		assert!(matches!(get(&interpreter, "c"), Literal::Boolean(true)));
+		assert_eq!(get_number(&interpreter, "a"), 1.0);
+	}
+
+	#[test]
+	fn comma_operator_yields_right_operand() {
+		let interpreter = run("var a = 0; var b = (a = 1, a + 1); var c = len((\"abc\", \"de\"));");
+		assert_eq!(get_number(&interpreter, "a"), 1.0);
+		assert_eq!(get_number(&interpreter, "b"), 2.0);
+		assert_eq!(get_number(&interpreter, "c"), 2.0);
+	}
+
+	#[test]
+	fn map_literals() {
+		let interpreter = run("var m = {\"a\": 1, \"b\": 2}; var a = m[\"a\"]; m[\"a\"] = 3; m[\"c\"] = 4; var n = len(m);");
+		assert_eq!(get_number(&interpreter, "a"), 1.0);
+		assert_eq!(get_number(&interpreter, "n"), 3.0);
+		assert_eq!(get(&interpreter, "m").to_string(), "{a: 3, b: 2, c: 4}");
+	}
+
+	#[test]
+	fn map_statement_and_errors() {
+		let interpreter = run("{\"a\": 1}; var e = {}; e[1] = true; var f = e[1];");
+		assert!(matches!(get(&interpreter, "f"), Literal::Boolean(true)));
+		assert!(run_err("var m = {\"a\": 1}; m[\"b\"];").get_msg().contains("Key not found"));
+		assert!(run_err("var m = {nil: 1};").get_msg().contains("Map keys must be strings or numbers"));
+		assert!(run_err("var m = {}; m[sqrt(-1)] = 1;").get_msg().contains("other than NaN"));
+	}
+
+	#[test]
+	fn maps_mix_string_and_number_keys() {
+		let interpreter = run("var m = {1: \"one\", \"1\": \"string one\", 0: \"zero\"}; m[2 - 1] = \"uno\";
+			var a = m[1]; var b = m[\"1\"]; var c = m[-0]; var n = len(m); var s = string(m);
+			var same = [1, \"a\"] == [1, \"a\"]; var arr = [1]; var self = arr == arr;");
+		assert!(matches!(get(&interpreter, "a"), Literal::String(ref s) if s == "uno"));
+		assert!(matches!(get(&interpreter, "b"), Literal::String(ref s) if s == "string one"));
+		assert!(matches!(get(&interpreter, "c"), Literal::String(ref s) if s == "zero"));
+		assert_eq!(get_number(&interpreter, "n"), 3.0);
+		assert!(matches!(get(&interpreter, "s"), Literal::String(ref s) if s == "{0: zero, 1: uno, 1: string one}"));
+		assert!(matches!(get(&interpreter, "same"), Literal::Boolean(false)));
+		assert!(matches!(get(&interpreter, "self"), Literal::Boolean(true)));
+	}
+
+	#[test]
+	fn functions_and_closures() {
+		let interpreter = run("fun add(a, b) { return a + b; } var a = add(1, 2);
+			fun counter() { var i = 0; fun inc() { i = i + 1; return i; } return inc; }
+			var c = counter(); c(); var b = c();
+			fun nothing() { } var n = nothing();");
+		assert_eq!(get_number(&interpreter, "a"), 3.0);
+		assert_eq!(get_number(&interpreter, "b"), 2.0);
+		assert!(matches!(get(&interpreter, "n"), Literal::Nil));
+	}
+
+	#[test]
+	fn class_fields_round_trip() {
+		let interpreter = run("class Point { } var p = Point(); p.x = 1; p.y = p.x + 1; var y = p.y;");
+		assert_eq!(get_number(&interpreter, "y"), 2.0);
+		assert_eq!(get(&interpreter, "p").to_string(), "<Point instance>");
+		assert!(run_err("class A { } A().x;").get_msg().contains("Undefined property"));
+		assert!(run_err("var a = 1; a.x = 2;").get_msg().contains("Only instances have fields"));
+	}
+
+	#[test]
+	fn class_methods_bind_this() {
+		let interpreter = run("class Counter {
+				init(start) { this.count = start; }
+				bump() { this.count = this.count + 1; return this.count; }
+			}
+			var c = Counter(5); var bump = c.bump; bump(); var n = c.bump(); var again = c.init(0).count;");
+		assert_eq!(get_number(&interpreter, "n"), 7.0);
+		assert_eq!(get_number(&interpreter, "again"), 0.0);
+		assert!(run_err("class A { init(x) { } } A();").get_msg().contains("Expected 1 arguments but got 0"));
+	}
+
+	#[test]
+	fn subclasses_inherit_and_call_super() {
+		let interpreter = run("class A { name() { return \"a\"; } greet() { return \"hi \" + this.name(); } }
+			class B < A { name() { return \"b\" + super.name(); } }
+			class C < B { }
+			var inherited = C().greet(); var direct = A().greet();");
+		assert_eq!(get_string(&interpreter, "inherited"), "hi ba");
+		assert_eq!(get_string(&interpreter, "direct"), "hi a");
+		assert!(run_err("var A = 1; class B < A { }").get_msg().contains("Superclass must be a class"));
+	}
+
+	#[test]
+	fn unbounded_recursion_is_a_clean_error() {
+		// The test thread's own stack, which runs out before the default
+		// depth is reached.
+		let err = run_err("fun f(n) { return f(n + 1); }\nf(0);");
+		assert_eq!(err.get_msg(), "Error: Stack overflow: maximum call depth exceeded, at: ')' on line 1");
+	}
+
+	#[test]
+	fn max_depth_is_configurable() {
+		let mut err_rep = ErrorReporter::new();
+		let src = "var total = 0; fun f(n) { if (n > 0) f(n - 1); total = total + 1; } f(5); f(5);";
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+
+		let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+		interpreter.set_max_depth(6);
+		assert!(interpret(&mut interpreter, &stmts).is_ok());
+		assert_eq!(get(&interpreter, "total"), Literal::Number(12.0));
+
+		interpreter.set_max_depth(5);
+		assert!(interpret(&mut interpreter, &stmts).is_err());
+	}
+
+	#[test]
+	fn non_finite_results_print_compare_or_fail() {
+		let src = "var big = pow(10, 308); var inf = big * 10; var nan = inf - inf;
+			var a = string(inf); var b = string(-inf); var c = string(nan);
+			var d = inf == big * 100; var e = nan == nan; var f = inf > big;";
+		let interpreter = run(src);
+		assert_eq!(get(&interpreter, "a"), Literal::String("inf".to_owned()));
+		assert_eq!(get(&interpreter, "b"), Literal::String("-inf".to_owned()));
+		assert_eq!(get(&interpreter, "c"), Literal::String("NaN".to_owned()));
+		assert_eq!(get(&interpreter, "d"), Literal::Boolean(true));
+		assert_eq!(get(&interpreter, "e"), Literal::Boolean(false));
+		assert_eq!(get(&interpreter, "f"), Literal::Boolean(true));
+
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new("var big = pow(10, 200); print big * big;".to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+		interpreter.set_finite_arithmetic(true);
+		match interpret(&mut interpreter, &stmts) {
+			Err(RuntimeError::InterpreterError(err)) => assert_eq!(err.get_reason(), "1e200 * 1e200 is not a finite number"),
+			_ => panic!("expected an infinite product to fail")
+		}
+	}
+
+	#[test]
+	fn strict_plus_refuses_mixed_operands() {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new("var a = 1 + \"x\";".to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+
+		let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+		assert!(interpret(&mut interpreter, &stmts).is_ok());
+		assert_eq!(get(&interpreter, "a"), Literal::String("1x".to_owned()));
+
+		interpreter.set_strict_plus(true);
+		match interpret(&mut interpreter, &stmts) {
+			Err(RuntimeError::InterpreterError(err)) => assert_eq!(err.get_reason(), "Operands must be two numbers or two strings"),
+			_ => panic!("expected mixed operands to fail")
+		}
+	}
+}
@@ -1,6 +1,3 @@
-#[macro_use]
-extern crate lazy_static;
-
 mod tokens;
 mod syntax;
 mod parser;
@@ -8,60 +5,1032 @@ mod interpreter;
 mod environment;
 mod context;
 mod functions;
+mod host;
+mod classes;
+mod optimize;
+mod metrics;
+mod symbols;
+
+use parser::Parser;
+use parser::ParserError;
 
+use std::io;
+use std::io::IsTerminal;
 use std::io::Write;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
+pub use tokens::{Literal, Scanner, Token, TokenType};
+pub use metrics::Metrics;
+pub use host::{HostFunction, HostValue, ConversionError};
+pub use interpreter::{Interpreter, InterpreterError, RuntimeError};
+pub use syntax::{Expr, ExprKind, Stmt, StmtKind, FunctionDecl, ExprVisitor, StmtVisitor, PrettyPrint};
 
-use parser::Parser;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+	Error,
+	Warning
+}
+
+// Whether a diagnostic came from checking the program before it ran, or from
+// running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+	Compile,
+	Runtime
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	pub line: usize,
+	pub column: Option<usize>,
+	pub place: String,
+	pub message: String,
+	pub severity: Severity,
+	pub phase: Phase,
+	pub source_line: Option<String>,
+	// The name of the source the diagnostic is about, when there are several.
+	pub file: Option<String>
+}
+
+impl Diagnostic {
+	fn new(line: usize, column: Option<usize>, place: &str, message: &str, severity: Severity, phase: Phase) -> Diagnostic {
+		Diagnostic {
+			line,
+			column,
+			place: place.to_owned(),
+			message: message.to_owned(),
+			severity,
+			phase,
+			source_line: None,
+			file: None
+		}
+	}
+
+	fn runtime(err: &RuntimeError) -> Diagnostic {
+		match err {
+			RuntimeError::InterpreterError(ie) => Diagnostic::new(ie.get_line(), Some(ie.get_column()), ie.get_lexeme(), ie.get_reason(), Severity::Error, Phase::Runtime),
+			RuntimeError::BreakSentinel(_) | RuntimeError::ContinueSentinel(_) | RuntimeError::ReturnSentinel(_) => Diagnostic::new(0, None, "", err.get_msg(), Severity::Error, Phase::Runtime),
+		}
+	}
+
+	// The message, then the offending source line when it is known, with a
+	// caret under the column when that is known too.
+	pub fn render(&self, color: bool) -> String {
+		let paint = |code: &str, text: &str| if color {
+			format!("\x1b[{}m{}\x1b[0m", code, text)
+		} else {
+			text.to_owned()
+		};
+
+		let location = match &self.file {
+			Some(file) => format!("{} line {}", file, self.line),
+			None => format!("line {}", self.line),
+		};
+		let location = paint("2", &format!("[{}]", location));
+		let mut out = match self.severity {
+			Severity::Error => format!("{} {} {}: {}",location,paint("1;31", "Error"),self.place,self.message),
+			Severity::Warning => format!("{} {}: {}",location,paint("1;33", "Warning"),self.message),
+		};
+
+		if let Some(source) = &self.source_line {
+			out.push_str(&format!("\n    {}", source));
+			if let Some(column) = self.column {
+				// Tabs are kept so the caret lines up however wide they render.
+				let pad: String = source.chars().take(column.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+				out.push_str(&format!("\n    {}{}", pad, paint("1;31", "^")));
+			}
+		}
+
+		out
+	}
+
+	pub fn print_to_stderr(&self) {
+		eprintln!("{}", self.render(stderr_has_color()));
+	}
+}
+
+static COLOR_OUTPUT: AtomicBool = AtomicBool::new(true);
 
-use tokens::Scanner;
+// Diagnostics printed to stderr are colored when it is a terminal, unless
+// NO_COLOR is set or color is turned off here.
+pub fn set_color_output(enabled: bool) {
+	COLOR_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+fn stderr_has_color() -> bool {
+	use_color(io::stderr().is_terminal())
+}
 
+fn use_color(is_terminal: bool) -> bool {
+	is_terminal && std::env::var_os("NO_COLOR").is_none() && COLOR_OUTPUT.load(Ordering::Relaxed)
+}
+
+// Receives every diagnostic as it is found. Embedders can implement this to
+// route diagnostics somewhere other than a list or stderr.
+pub trait Reporter {
+	fn report(&mut self, diagnostic: Diagnostic);
+
+	fn had_error(&self) -> bool;
+}
+
+// Shorthands the scanner, parser and checker use to build diagnostics.
+impl dyn Reporter + '_ {
+	fn error(&mut self, line: usize, message: &str) {
+		self.report(Diagnostic::new(line, None, "", message, Severity::Error, Phase::Compile))
+	}
+
+	fn error_at(&mut self, line: usize, column: usize, message: &str) {
+		self.report(Diagnostic::new(line, Some(column), "", message, Severity::Error, Phase::Compile))
+	}
+
+	fn error_at_place(&mut self, line: usize, column: usize, place: &str, message: &str) {
+		self.report(Diagnostic::new(line, Some(column), place, message, Severity::Error, Phase::Compile))
+	}
+
+	fn warning(&mut self, line: usize, message: &str) {
+		self.report(Diagnostic::new(line, None, "", message, Severity::Warning, Phase::Compile))
+	}
+}
+
+fn source_lines(src: &str) -> Vec<String> {
+	src.lines().map(|l| l.to_owned()).collect()
+}
+
+// Quotes the line a diagnostic points at, if the reporter knows the source.
+fn attach_source(mut diagnostic: Diagnostic, source_lines: &[String]) -> Diagnostic {
+	if diagnostic.source_line.is_none() {
+		diagnostic.source_line = diagnostic.line.checked_sub(1).and_then(|i| source_lines.get(i)).cloned();
+	}
+	diagnostic
+}
+
+// Collects diagnostics so they can be handed back once a run is over.
 pub struct ErrorReporter {
-	had_error: bool
+	had_error: bool,
+	diagnostics: Vec<Diagnostic>,
+	source_lines: Vec<String>
+}
+
+impl Default for ErrorReporter {
+	fn default() -> ErrorReporter {
+		ErrorReporter::new()
+	}
 }
 
 impl ErrorReporter {
 
 	pub fn new() -> ErrorReporter {
+		ErrorReporter::with_source("")
+	}
+
+	// Keeps the source so diagnostics can quote the line they point at.
+	pub fn with_source(src: &str) -> ErrorReporter {
 		ErrorReporter {
-			had_error: false
+			had_error: false,
+			diagnostics: Vec::new(),
+			source_lines: source_lines(src)
 		}
 	}
 
-	fn error(&mut self, line: usize, message: &str) {
-		self.report(line,"",message)
+	pub fn diagnostics(&self) -> &[Diagnostic] {
+		&self.diagnostics
 	}
 
-	fn report(&mut self,line: usize, place: &str, msg: &str) {
-		eprintln!("[line {}] Error {}: {}",line,place,msg);
-		self.had_error = true;
+	pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+		self.diagnostics
 	}
 
+	pub fn print_to_stderr(&self) {
+		for diagnostic in self.diagnostics.iter() {
+			diagnostic.print_to_stderr();
+		}
+	}
+
+}
+
+impl Reporter for ErrorReporter {
+	fn report(&mut self, diagnostic: Diagnostic) {
+		if diagnostic.severity == Severity::Error {
+			self.had_error = true;
+		}
+		self.diagnostics.push(attach_source(diagnostic, &self.source_lines));
+	}
+
+	fn had_error(&self) -> bool {
+		self.had_error
+	}
+}
+
+// Prints each diagnostic to stderr as soon as it is reported.
+pub struct StderrReporter {
+	had_error: bool,
+	source_lines: Vec<String>
+}
+
+impl StderrReporter {
+	pub fn new() -> StderrReporter {
+		StderrReporter::with_source("")
+	}
+
+	pub fn with_source(src: &str) -> StderrReporter {
+		StderrReporter {
+			had_error: false,
+			source_lines: source_lines(src)
+		}
+	}
+}
+
+impl Default for StderrReporter {
+	fn default() -> StderrReporter {
+		StderrReporter::new()
+	}
 }
-pub fn run(src: String) {
-	let mut err_hand = ErrorReporter::new();
+
+impl Reporter for StderrReporter {
+	fn report(&mut self, diagnostic: Diagnostic) {
+		if diagnostic.severity == Severity::Error {
+			self.had_error = true;
+		}
+		attach_source(diagnostic, &self.source_lines).print_to_stderr();
+	}
+
+	fn had_error(&self) -> bool {
+		self.had_error
+	}
+}
+
+// Adapts a print callback to the interpreter's output sink. Every print is
+// written in a single call, so the callback fires once per print.
+struct CallbackWriter<'a> {
+	callback: &'a mut dyn FnMut(&str)
+}
+
+impl Write for CallbackWriter<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		(self.callback)(&String::from_utf8_lossy(buf));
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+	pub scan: Duration,
+	pub parse: Duration,
+	pub check: Duration,
+	pub interpret: Duration
+}
+
+// How run_with_options treats the program. The default runs it as is.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+	// Print the value of a line holding only an expression, as run_repl does.
+	pub echo: bool,
+	// Fold constant expressions before running.
+	pub optimize: bool,
+	// Calls nested deeper than this stop with a runtime error.
+	pub max_depth: usize,
+	// Calls may pass at most this many arguments, or any number with None.
+	pub max_args: Option<usize>,
+	// Make `+` take two numbers or two strings, rather than converting a
+	// lone number to a string.
+	pub strict_plus: bool,
+	// Fail arithmetic that yields an infinity or NaN.
+	pub finite_arithmetic: bool,
+	// The file the source came from. Imports resolve relative to it, or to
+	// the working directory without one.
+	pub script_path: Option<PathBuf>
+}
+
+impl Default for RunOptions {
+	fn default() -> RunOptions {
+		RunOptions {
+			echo: false,
+			optimize: false,
+			max_depth: interpreter::DEFAULT_MAX_DEPTH,
+			max_args: Some(parser::MAX_ARGS),
+			strict_plus: false,
+			finite_arithmetic: false,
+			script_path: None
+		}
+	}
+}
+
+impl RunOptions {
+	// Gives the interpreter the depth limit and arithmetic rules asked for.
+	pub fn configure(&self, interpreter: &mut Interpreter) {
+		interpreter.set_max_depth(self.max_depth);
+		interpreter.set_strict_plus(self.strict_plus);
+		interpreter.set_finite_arithmetic(self.finite_arithmetic);
+	}
+}
+
+// Calls recurse on the native stack, several kilobytes at a time in a debug
+// build, so the entry points that own their input run it on a thread with
+// room for the default depth many times over.
+pub const STACK_SIZE: usize = 64 << 20;
+
+// Runs f on a thread with a STACK_SIZE stack, or right here when this thread
+// already is one. Calls made there may use all but its last megabyte.
+pub fn with_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+	let budget = STACK_SIZE - (1 << 20);
+	if interpreter::stack_budget() >= budget {
+		return f();
+	}
+
+	thread::scope(|scope| {
+		let worker = thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, || {
+			interpreter::set_stack_budget(budget);
+			f()
+		}).expect("failed to start a thread to run on");
+		worker.join().unwrap_or_else(|payload| panic::resume_unwind(payload))
+	})
+}
+
+pub fn run(src: String) -> Vec<Diagnostic> {
+	with_large_stack(|| collect(src, Interpreter::new(), RunOptions::default()).0)
+}
+
+// Runs named sources one after another in a single interpreter, so each sees
+// the globals the ones before it defined. Every source runs, and the
+// diagnostics name the source they belong to.
+pub fn run_all(sources: Vec<(String, String)>) -> Result<(), Vec<Diagnostic>> {
+	with_large_stack(|| run_all_in(sources, Interpreter::new()))
+}
+
+fn run_all_in(sources: Vec<(String, String)>, mut interpreter: Interpreter) -> Result<(), Vec<Diagnostic>> {
+	let mut diagnostics = Vec::new();
+
+	for (name, src) in sources {
+		let mut err_hand = ErrorReporter::with_source(&src);
+		run_with(src, &mut interpreter, RunOptions::default(), &mut err_hand);
+		diagnostics.extend(err_hand.into_diagnostics().into_iter().map(|d| Diagnostic { file: Some(name.clone()), ..d }));
+	}
+
+	if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+		Err(diagnostics)
+	} else {
+		Ok(())
+	}
+}
+
+// Runs the source in an interpreter the caller keeps, so globals and host
+// functions registered on it carry over between runs. This and the other
+// entry points borrowing from the caller run on the caller's thread, where
+// calls stop short of overflowing its stack sooner.
+pub fn run_in(src: String, interpreter: &mut Interpreter) -> Vec<Diagnostic> {
+	run_in_with_options(src, interpreter, RunOptions::default()).0
+}
+
+// Like run_in, but with the options for how to treat the program. The
+// interpreter keeps its own settings; RunOptions::configure applies the
+// ones in the options to it.
+pub fn run_in_with_options(src: String, interpreter: &mut Interpreter, options: RunOptions) -> (Vec<Diagnostic>, Timings) {
+	let mut err_hand = ErrorReporter::with_source(&src);
+	let timings = run_with(src, interpreter, options, &mut err_hand);
+	(err_hand.into_diagnostics(), timings)
+}
+
+// Runs the source, sending diagnostics to the given reporter as they are
+// found rather than collecting them.
+pub fn run_reporting(src: String, reporter: &mut dyn Reporter) {
+	run_with(src, &mut Interpreter::new(), RunOptions::default(), reporter);
+}
+
+// Like run, but a line holding only an expression prints its value the way
+// an interactive prompt would. Assignments stay quiet, and a missing final
+// semicolon is supplied.
+pub fn run_repl(src: String) -> Vec<Diagnostic> {
+	run_with_options(src, RunOptions { echo: true, ..RunOptions::default() }).0
+}
+
+// True when the source parses up to its end without error, but stops
+// mid-statement, so an interactive prompt should read more lines. Input that
+// only lacks its final semicolon counts as complete.
+pub fn is_incomplete(src: &str) -> bool {
+	let parse_err = |src: String| {
+		let mut err_hand = ErrorReporter::with_source(&src);
+		let tokens = Scanner::new(src,&mut err_hand).scan_tokens();
+		Parser::new(tokens,&mut err_hand).parse().err()
+	};
+
+	parse_err(src.to_owned()) == Some(ParserError::UnexpectedEof) && parse_err(supply_semicolon(src.to_owned())).is_some()
+}
+
+fn supply_semicolon(src: String) -> String {
+	let trimmed = src.trim_end();
+	if trimmed.is_empty() || trimmed.ends_with(';') || trimmed.ends_with('}') {
+		src
+	} else {
+		format!("{};", trimmed)
+	}
+}
+
+pub fn run_timed(src: String) -> (Vec<Diagnostic>, Timings) {
+	with_large_stack(|| collect(src, Interpreter::new(), RunOptions::default()))
+}
+
+// Like run_timed, but constant expressions are folded before running.
+pub fn run_optimized(src: String) -> (Vec<Diagnostic>, Timings) {
+	run_with_options(src, RunOptions { optimize: true, ..RunOptions::default() })
+}
+
+pub fn run_with_options(src: String, options: RunOptions) -> (Vec<Diagnostic>, Timings) {
+	with_large_stack(|| collect(src, Interpreter::new(), options))
+}
+
+pub fn run_to(src: String, out: &mut dyn Write) -> Vec<Diagnostic> {
+	collect(src, Interpreter::with_output(Box::new(out)), RunOptions::default()).0
+}
+
+pub fn run_streaming(src: String, on_print: &mut dyn FnMut(&str)) -> Vec<Diagnostic> {
+	let out = CallbackWriter {
+		callback: on_print
+	};
+	collect(src, Interpreter::with_output(Box::new(out)), RunOptions::default()).0
+}
+
+fn echo_bare_expression(mut stmts: Vec<Stmt>) -> Vec<Stmt> {
+	if stmts.len() != 1 {
+		return stmts;
+	}
+
+	let stmt = stmts.pop().unwrap();
+	match stmt.kind {
+		StmtKind::Expr(expr) if !matches!(expr.kind, ExprKind::Assignment(..) | ExprKind::SetIndex(..) | ExprKind::Set(..)) => {
+			vec![Stmt::new(StmtKind::Print(expr), stmt.span)]
+		},
+		kind => vec![Stmt::new(kind, stmt.span)]
+	}
+}
+
+// Evaluates a single expression, without a trailing semicolon, in a fresh
+// interpreter. Every problem comes back as diagnostics instead of printing.
+pub fn eval_expression(src: &str) -> Result<Literal, Vec<Diagnostic>> {
+	let mut err_hand = ErrorReporter::with_source(src);
+	let tokens = Scanner::new(src.to_owned(),&mut err_hand).scan_tokens();
+	let expr = match Parser::new(tokens,&mut err_hand).parse_expression() {
+		Ok(expr) if !err_hand.had_error => expr,
+		_ => return Err(err_hand.into_diagnostics())
+	};
+
+	for err in context::check_expr(&expr) {
+		err.report(&mut err_hand)
+	}
+	if err_hand.had_error {
+		return Err(err_hand.into_diagnostics());
+	}
+
+	expr.accept(&mut Interpreter::new()).map_err(|er| {
+		err_hand.report(Diagnostic::runtime(&er));
+		err_hand.into_diagnostics()
+	})
+}
+
+// Lists every scanned token with its line, type and lexeme, alongside any
+// scan errors. Tokens are produced even when scanning reported errors.
+pub fn dump_tokens(src: String) -> (String, Vec<Diagnostic>) {
+	let mut err_hand = ErrorReporter::with_source(&src);
+	let tokens = Scanner::new(src,&mut err_hand).scan_tokens();
+	let mut total = String::new();
+
+	for tk in tokens.iter() {
+		total.push_str(&format!("{:<4} {:?} '{}'\n", tk.get_line(), tk.get_type(), tk));
+	}
+
+	(total, err_hand.into_diagnostics())
+}
+
+// Scans and parses the source, handing back the syntax tree to walk with an
+// ExprVisitor or StmtVisitor.
+pub fn parse(src: String) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+	let mut err_hand = ErrorReporter::with_source(&src);
 
 	let scanner = Scanner::new(src,&mut err_hand);
 	let tokens = scanner.scan_tokens();
 	let mut parser = Parser::new(tokens,&mut err_hand);
 	let stmts = parser.parse();
 
+	match stmts {
+		Ok(stmts) if !err_hand.had_error => Ok(stmts),
+		_ => Err(err_hand.into_diagnostics())
+	}
+}
+
+// Scans, parses and checks the source without running it, collecting every
+// diagnostic. Problems that only show up at runtime pass.
+pub fn check_only(src: String) -> Vec<Diagnostic> {
+	let mut err_hand = ErrorReporter::with_source(&src);
+	let tokens = Scanner::new(src,&mut err_hand).scan_tokens();
+
+	if let Ok(stmts) = Parser::new(tokens,&mut err_hand).parse() {
+		for err in context::check(&stmts, false, Interpreter::new().global_names()) {
+			err.report(&mut err_hand)
+		}
+	}
+
+	err_hand.into_diagnostics()
+}
+
+// Renders an expression in the S-expression form described on PrettyPrint.
+pub fn format_expr(expr: &Expr) -> String {
+	expr.accept(&PrettyPrint)
+}
+
+// Scans and parses without executing, rendering one statement per line.
+pub fn dump_ast(src: String) -> Result<String, Vec<Diagnostic>> {
+	Ok(render_ast(&parse(src)?))
+}
+
+// Measures the parsed program without running it.
+pub fn metrics(src: String) -> Result<Metrics, Vec<Diagnostic>> {
+	Ok(metrics::count(&parse(src)?))
+}
+
+// Like dump_ast, showing the tree after constant folding.
+pub fn dump_optimized_ast(src: String) -> Result<String, Vec<Diagnostic>> {
+	Ok(render_ast(&optimize::optimize(&parse(src)?, false)))
+}
+
+fn render_ast(stmts: &[Stmt]) -> String {
+	let mut total = String::new();
+
+	for stmt in stmts.iter() {
+		total.push_str(&format!("{}\n", stmt));
+	}
+
+	total
+}
+
+#[cfg(feature = "serde")]
+pub fn parse_to_json(src: String) -> Result<String, Vec<Diagnostic>> {
+	let stmts = parse(src)?;
+	Ok(serde_json::to_string(&stmts).expect("syntax trees always serialize"))
+}
+
+#[cfg(feature = "serde")]
+pub fn parse_to_optimized_json(src: String) -> Result<String, Vec<Diagnostic>> {
+	let stmts = optimize::optimize(&parse(src)?, false);
+	Ok(serde_json::to_string(&stmts).expect("syntax trees always serialize"))
+}
+
+// Scans the source into a JSON array of {type, lexeme, line, column} objects,
+// ending with the Eof token.
+#[cfg(feature = "serde")]
+pub fn dump_tokens_json(src: String) -> (String, Vec<Diagnostic>) {
+	let mut err_hand = ErrorReporter::with_source(&src);
+	let tokens = Scanner::new(src,&mut err_hand).scan_tokens();
+	let tokens: Vec<_> = tokens.iter().map(|tk| serde_json::json!({
+		"type": tk.get_type(),
+		"lexeme": tk.get_lexeme(),
+		"line": tk.get_line(),
+		"column": tk.get_column()
+	})).collect();
+
+	(serde_json::to_string(&tokens).expect("tokens always serialize"), err_hand.into_diagnostics())
+}
+
+// The entry points that make their own interpreter set it up from the
+// options. One the caller passes in keeps the settings it was given.
+fn collect(src: String, mut interpreter: Interpreter, options: RunOptions) -> (Vec<Diagnostic>, Timings) {
+	options.configure(&mut interpreter);
+	let mut err_hand = ErrorReporter::with_source(&src);
+	let timings = run_with(src, &mut interpreter, options, &mut err_hand);
+	(err_hand.into_diagnostics(), timings)
+}
+
+fn run_with(src: String, interpreter: &mut Interpreter, options: RunOptions, err_hand: &mut dyn Reporter) -> Timings {
+	let mut timings = Timings::default();
+	// The reporter already holds the source as typed, so diagnostics don't
+	// show the supplied semicolon.
+	let src = if options.echo { supply_semicolon(src) } else { src };
+	if let Some(path) = &options.script_path {
+		interpreter.set_script_path(path);
+	}
+
+	let start = Instant::now();
+	let scanner = Scanner::new(src,err_hand);
+	let tokens = scanner.scan_tokens();
+	timings.scan = start.elapsed();
+
+	let start = Instant::now();
+	let mut parser = Parser::new(tokens,err_hand).with_max_args(options.max_args);
+	let stmts = parser.parse();
+	let stmts = if options.echo { stmts.map(echo_bare_expression) } else { stmts };
+	// Folding counts towards parsing, as it only reshapes the tree.
+	let stmts = if options.optimize { stmts.map(|stmts| optimize::optimize(&stmts, interpreter.strict_plus())) } else { stmts };
+	timings.parse = start.elapsed();
+
 	if let Ok(stmts) = &stmts {
-		let context_errors = context::check(stmts);
+		let start = Instant::now();
+		let context_errors = context::check(stmts, false, interpreter.global_names());
 		for err in context_errors {
-			err.report(&mut err_hand)
+			err.report(err_hand)
 		}
+		timings.check = start.elapsed();
 	}
 	
-	match stmts {
-		Ok(ref stmts) if !err_hand.had_error => {
-			let res = interpreter::interpret(stmts);
-			match res {
-				Err(er) => eprintln!("{}",er.get_msg()),
-				Ok(_) => (),
+	if let Ok(ref stmts) = stmts {
+		if !err_hand.had_error() {
+			let start = Instant::now();
+			if let Err(er) = interpreter::interpret(interpreter, stmts) {
+				err_hand.report(Diagnostic::runtime(&er));
 			}
-		},
-		_ => { std::io::stderr().flush().unwrap(); return;},
+			timings.interpret = start.elapsed();
+		}
+	}
+
+	timings
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn collects_scan_and_parse_diagnostics() {
+		let diagnostics = run("var a = 1 @;\nprint ;".to_owned());
+		assert_eq!(diagnostics.len(), 2);
+
+		assert_eq!(diagnostics[0].line, 1);
+		assert_eq!(diagnostics[0].message, "Unexpected character.");
+		assert_eq!(diagnostics[0].severity, Severity::Error);
+		assert_eq!(diagnostics[0].phase, Phase::Compile);
+
+		assert_eq!(diagnostics[1].line, 2);
+		assert_eq!(diagnostics[1].place, ";");
+		assert_eq!(diagnostics[1].message, "Unexpected token");
+		assert_eq!(diagnostics[1].severity, Severity::Error);
+	}
+
+	#[test]
+	fn reports_every_parse_error() {
+		let diagnostics = run("print ;\nvar = 1;\nprint 1;\nvar a = (;".to_owned());
+		let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+		assert_eq!(lines, vec![1, 2, 4]);
+		assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+	}
+
+	#[test]
+	fn runtime_errors_are_diagnostics() {
+		let diagnostics = run("var a = 1 / 0;".to_owned());
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(diagnostics[0].place, "/");
+		assert_eq!(diagnostics[0].message, "Division by zero");
+		assert_eq!(diagnostics[0].phase, Phase::Runtime);
+	}
+
+	#[test]
+	fn streams_each_print() {
+		let mut printed = Vec::new();
+		let diagnostics = run_streaming("print 1; var a = \"two\"; print a; print [3];".to_owned(), &mut |s| printed.push(s.to_owned()));
+		assert!(diagnostics.is_empty());
+		assert_eq!(printed, vec!["1\n", "two\n", "[3]\n"]);
+	}
+
+	#[test]
+	fn runs_to_writer() {
+		let mut out = Vec::new();
+		run_to("print 1 + 2;".to_owned(), &mut out);
+		assert_eq!(String::from_utf8(out).unwrap(), "3\n");
+	}
+
+	#[test]
+	fn write_has_no_newline() {
+		let mut out = Vec::new();
+		let diagnostics = run_to("write(\"a\"); write(\"b\"); write(1);".to_owned(), &mut out);
+		assert!(diagnostics.is_empty());
+		assert_eq!(String::from_utf8(out).unwrap(), "ab1");
+	}
+
+	// Names are resolved to slots before running, but a local whose
+	// declaration hasn't run yet still falls through to outer scopes.
+	#[test]
+	fn resolved_names_keep_their_scoping() {
+		let mut out = Vec::new();
+		let src = "var a = \"outer\";\n{ print a; var b = 1; var a = a + \"!\"; print a; }\n{ fun f() { return c; } var c = \"later\"; print f(); }\nclass A { get() { return \"A\"; } }\n{ class B < A { get() { return super.get() + this.n; } } var k = B(); k.n = 1; print k.get(); }";
+		let diagnostics = run_to(src.to_owned(), &mut out);
+		assert!(diagnostics.is_empty());
+		assert_eq!(String::from_utf8(out).unwrap(), "outer\nouter!\nlater\nA1\n");
+	}
+
+	// run moves to a thread of its own, so a test thread's small stack holds
+	// the default depth even with each call nested deep in its body.
+	#[test]
+	fn default_depth_is_reachable() {
+		let src = |calls: usize| format!("fun f(n) {{ if (true) {{ {{ while (true) {{ {{ if (n < {}) {{ return 1 + (2 * (3 + f(n + 1))); }} return 0; }} }} }} }} }}\nvar x = f(1);", calls);
+		assert!(run(src(interpreter::DEFAULT_MAX_DEPTH)).is_empty());
+
+		let diagnostics = run(src(interpreter::DEFAULT_MAX_DEPTH + 1));
+		assert_eq!(diagnostics[0].message, "Stack overflow: maximum call depth exceeded");
+	}
+
+	// run_to stays on the test thread's own stack, which gives out long
+	// before the depth limit when each call is nested deep in its body.
+	#[test]
+	fn unbounded_recursion_is_a_clean_error() {
+		let nested = "fun f(n) { if (true) { { while (true) { { return 1 + (2 * (3 + f(n + 1))); } } } } }\nprint f(0);";
+		let method = "class A { m(n) { return this.m(n + 1); } }\nprint A().m(0);";
+		for src in [nested, method] {
+			let mut out = Vec::new();
+			let diagnostics = run_to(src.to_owned(), &mut out);
+			assert_eq!(diagnostics.len(), 1);
+			assert_eq!(diagnostics[0].message, "Stack overflow: maximum call depth exceeded");
+			assert!(out.is_empty());
+		}
+	}
+
+	#[test]
+	fn timed_run_reports_phases() {
+		let (diagnostics, timings) = run_timed("var a = 0; while (a < 100) a = a + 1;".to_owned());
+		assert!(diagnostics.is_empty());
+
+		let total = timings.scan + timings.parse + timings.check + timings.interpret;
+		assert!(total >= timings.interpret);
+		assert!(timings.interpret > Duration::from_secs(0));
+	}
+
+	#[test]
+	fn for_clause_errors_keep_their_lines() {
+		let diagnostics = run_to("var z = 0;\nfor (var i = 0;\n  i < 3;\n  i = i / z) {\n  print i;\n}".to_owned(), &mut io::sink());
+		assert_eq!((diagnostics[0].line, diagnostics[0].column, diagnostics[0].message.as_str()), (4, Some(9), "Division by zero"));
+
+		let diagnostics = run_to("for (var i = 0;\n  i < \"a\";\n  i = i + 1) { }".to_owned(), &mut io::sink());
+		assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, Some(5)));
+	}
+
+	#[test]
+	fn dumps_ast_without_running() {
+		let dump = dump_ast("var a = 1 / 0;\nwhile (a) print a;".to_owned());
+		assert_eq!(dump.unwrap(), "(var a (/ 1 0))\n(while a (print a))\n");
+
+		let diagnostics = dump_ast("print ;".to_owned()).unwrap_err();
+		assert_eq!(diagnostics.len(), 1);
+	}
+
+	#[test]
+	fn formats_parsed_expressions() {
+		let stmts = parse("(1 + 2) * -x[1:];".to_owned()).unwrap();
+		match &stmts[0].kind {
+			StmtKind::Expr(expr) => assert_eq!(format_expr(expr), "(* (group (+ 1 2)) (- (slice x 1 _)))"),
+			_ => panic!("expected an expression statement, got {}", stmts[0])
+		}
+	}
+
+	#[test]
+	fn measures_without_running() {
+		let measured = metrics("while (true) { print 1 / 0; }".to_owned()).unwrap();
+		assert_eq!(measured, Metrics { statements: 3, expressions: 4, max_depth: 1, functions: 0 });
+		assert!(metrics("print ;".to_owned()).is_err());
+	}
+
+	#[test]
+	fn checks_without_running() {
+		assert!(check_only("print 1 / 0; print len(\"a\");".to_owned()).is_empty());
+		assert_eq!(check_only("print ;".to_owned())[0].severity, Severity::Error);
+		assert_eq!(check_only("return 1;".to_owned())[0].message, "Cannot return from top-level code.");
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn parses_to_json() {
+		let json = parse_to_json("var a = [1, \"b\", nil, true];".to_owned()).unwrap();
+		let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+		assert_eq!(value[0]["span"], serde_json::json!({"start": 0, "end": 28}));
+		let var = &value[0]["kind"]["Var"];
+		assert_eq!(var[0]["lexeme"], "a");
+		assert_eq!(var[0]["t_type"], "Identifier");
+		assert_eq!(var[1]["span"], serde_json::json!({"start": 8, "end": 27}));
+		let elements: Vec<_> = var[1]["kind"]["Array"].as_array().unwrap().iter().map(|el| el["kind"].clone()).collect();
+		assert_eq!(elements, vec![serde_json::json!({"Literal": 1.0}), serde_json::json!({"Literal": "b"}), serde_json::json!({"Literal": null}), serde_json::json!({"Literal": true})]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn dumps_tokens_as_json() {
+		let src = "var a = \"b\";\n  print a+1;";
+		let (json, diagnostics) = dump_tokens_json(src.to_owned());
+		assert!(diagnostics.is_empty());
+
+		let value: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+		let scanned = Scanner::new(src.to_owned(), &mut ErrorReporter::new()).scan_tokens();
+		assert_eq!(value.len(), scanned.len());
+		for (parsed, tk) in value.iter().zip(scanned.iter()) {
+			assert_eq!(parsed["type"], serde_json::to_value(tk.get_type()).unwrap());
+			assert_eq!(parsed["lexeme"], tk.get_lexeme());
+			assert_eq!(parsed["line"], tk.get_line());
+			assert_eq!(parsed["column"], tk.get_column());
+		}
+		assert_eq!(value[3], serde_json::json!({"type": {"Literal": "b"}, "lexeme": "\"b\"", "line": 1, "column": 9}));
+		assert_eq!(value[6]["column"], 9);
+	}
+
+	#[test]
+	fn dumps_tokens_despite_errors() {
+		let (tokens, diagnostics) = dump_tokens("var a = @\"b\";".to_owned());
+		assert_eq!(diagnostics.len(), 1);
+
+		let lines: Vec<&str> = tokens.lines().collect();
+		assert_eq!(lines, vec![
+			"1    Var 'var'",
+			"1    Identifier 'a'",
+			"1    Equal '='",
+			"1    Literal(String(\"b\")) '\"b\"'",
+			"1    Semicolon ';'",
+			"1    Eof ''",
+		]);
+	}
+
+	#[test]
+	fn repl_echoes_bare_expressions() {
+		assert_eq!(dump_ast("1 + 2;".to_owned()).unwrap(), "(expr (+ 1 2))\n");
+
+		let echoed = |src: &str| parse(src.to_owned()).map(echo_bare_expression).unwrap().into_iter().map(|stmt| stmt.kind).collect::<Vec<_>>();
+		assert!(matches!(echoed("1 + 2;")[..], [StmtKind::Print(_)]));
+		assert!(matches!(echoed("a = 2;")[..], [StmtKind::Expr(_)]));
+		assert!(matches!(echoed("a[0] = 2;")[..], [StmtKind::Expr(_)]));
+		assert!(matches!(echoed("obj.x = 2;")[..], [StmtKind::Expr(_)]));
+		assert!(matches!(echoed("var a = 2;")[..], [StmtKind::Var(..)]));
+		assert!(matches!(echoed("1; 2;")[..], [StmtKind::Expr(_), StmtKind::Expr(_)]));
+	}
+
+	// The way the prompt runs each entry: one interpreter for the session,
+	// with echo on.
+	#[test]
+	fn repl_session_keeps_globals() {
+		let mut out = Vec::new();
+		{
+			let mut interpreter = Interpreter::with_output(Box::new(&mut out));
+			let options = RunOptions { echo: true, ..RunOptions::default() };
+			for entry in ["var x = 1;", "x + 2", "fun f() { return x * 10; }", "f()", "var x = \"again\";", "x"] {
+				assert!(run_in_with_options(entry.to_owned(), &mut interpreter, options.clone()).0.is_empty());
+			}
+
+			let (diagnostics, _) = run_in_with_options("if (true) {".to_owned(), &mut interpreter, options);
+			assert_eq!(diagnostics[0].source_line.as_deref(), Some("if (true) {"));
+		}
+		assert_eq!(String::from_utf8(out).unwrap(), "3\n10\nagain\n");
+	}
+
+	#[test]
+	fn detects_incomplete_input() {
+		assert!(is_incomplete("if (x) {\n"));
+		assert!(is_incomplete("if (x) {\n  print x;\n"));
+		assert!(!is_incomplete("if (x) {\n  print x;\n}\n"));
+		assert!(!is_incomplete("1 + 2\n"));
+		assert!(is_incomplete("print (1 +\n"));
+		assert!(!is_incomplete("print 1 +;\n"));
 	}
 
-}
\ No newline at end of file
+	#[test]
+	fn renders_source_line_with_caret() {
+		let diagnostics = run("var a = 1;\n\tprint a +;".to_owned());
+		assert_eq!(diagnostics[0].column, Some(11));
+		assert_eq!(diagnostics[0].render(false), "[line 2] Error ;: Unexpected token\n    \tprint a +;\n    \t         ^");
+
+		let diagnostics = run("print \"a\" - 1;".to_owned());
+		assert_eq!(diagnostics[0].render(false), "[line 1] Error -: Expected number\n    print \"a\" - 1;\n              ^");
+		assert!(diagnostics[0].render(true).starts_with("\x1b[2m[line 1]\x1b[0m \x1b[1;31mError\x1b[0m"));
+		assert!(!use_color(false));
+		assert!(!diagnostics[0].render(use_color(false)).contains('\x1b'));
+
+		let diagnostics = run("var a = 1;\nwhile (a) { }\nbreak;".to_owned());
+		assert_eq!(diagnostics[0].render(false), "[line 3] Error : Break found outside of loop body.\n    break;");
+	}
+
+	#[test]
+	fn evaluates_single_expressions() {
+		assert!(matches!(eval_expression("2 + 3 * 4"), Ok(Literal::Number(x)) if x == 14.0));
+		assert!(matches!(eval_expression("len(\"abc\") > 2 ? \"long\" : \"short\""), Ok(Literal::String(ref s)) if s == "long"));
+
+		let diagnostics = eval_expression("1 + 2;").unwrap_err();
+		assert_eq!(diagnostics[0].message, "Expected end of expression");
+		assert_eq!(eval_expression("1 / 0").unwrap_err()[0].message, "Division by zero");
+		assert_eq!(eval_expression("x").unwrap_err()[0].message, "Undefined variable 'x'");
+		assert_eq!(eval_expression("super.f").unwrap_err()[0].message, "Cannot use 'super' outside of a subclass.");
+	}
+
+	#[test]
+	fn batch_shares_globals_and_names_files() {
+		let mut out = Vec::new();
+		let sources = vec![
+			("a.lox".to_owned(), "fun greet(name) { return \"hi \" + name; }".to_owned()),
+			("b.lox".to_owned(), "print greet(\"b\");".to_owned()),
+		];
+		assert!(run_all_in(sources, Interpreter::with_output(Box::new(&mut out))).is_ok());
+		assert_eq!(String::from_utf8(out).unwrap(), "hi b\n");
+
+		let sources = vec![
+			("a.lox".to_owned(), "var a = 1;\nprint a +;".to_owned()),
+			("b.lox".to_owned(), "print 1 / 0;".to_owned()),
+		];
+		let diagnostics = run_all_in(sources, Interpreter::with_output(Box::new(io::sink()))).unwrap_err();
+		let files: Vec<Option<&str>> = diagnostics.iter().map(|d| d.file.as_deref()).collect();
+		assert_eq!(files, vec![Some("a.lox"), Some("b.lox")]);
+		assert_eq!(diagnostics[1].render(false), "[b.lox line 1] Error /: Division by zero\n    print 1 / 0;\n            ^");
+	}
+
+	#[test]
+	fn host_functions_keep_state() {
+		struct Counter(std::cell::Cell<f64>);
+
+		impl HostFunction for Counter {
+			fn arity(&self) -> usize {
+				1
+			}
+
+			fn call(&self, args: Vec<Literal>) -> Result<Literal, String> {
+				match args[0] {
+					Literal::Number(step) => {
+						self.0.set(self.0.get() + step);
+						Ok(Literal::Number(self.0.get()))
+					},
+					_ => Err("count() expects a number".to_owned())
+				}
+			}
+		}
+
+		let mut out = Vec::new();
+		{
+			let mut interpreter = Interpreter::with_output(Box::new(&mut out));
+			interpreter.register_host("count", Box::new(Counter(std::cell::Cell::new(0.0))));
+			assert!(run_in("count(1); count(2); print count(3); print count;".to_owned(), &mut interpreter).is_empty());
+			assert!(run_in("print typeof(count); print count(10);".to_owned(), &mut interpreter).is_empty());
+
+			let diagnostics = run_in("count(\"a\");".to_owned(), &mut interpreter);
+			assert_eq!(diagnostics[0].message, "count() expects a number");
+			assert_eq!(diagnostics[0].phase, Phase::Runtime);
+			assert_eq!(run_in("count();".to_owned(), &mut interpreter)[0].message, "Expected 1 arguments but got 0");
+		}
+		assert_eq!(String::from_utf8(out).unwrap(), "6\n<native fn count>\nfunction\n16\n");
+	}
+
+	#[test]
+	fn run_in_keeps_the_interpreter_settings() {
+		let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+		interpreter.set_strict_plus(true);
+		interpreter.set_finite_arithmetic(true);
+		interpreter.set_max_depth(5);
+
+		assert_eq!(run_in("print 1 + \"x\";".to_owned(), &mut interpreter)[0].message, "Operands must be two numbers or two strings");
+		assert_eq!(run_in("var big = pow(10, 308); print big * 10;".to_owned(), &mut interpreter).len(), 1);
+		let diagnostics = run_in("fun f(n) { if (n > 0) f(n - 1); } f(5);".to_owned(), &mut interpreter);
+		assert_eq!(diagnostics[0].message, "Stack overflow: maximum call depth exceeded");
+	}
+
+	#[test]
+	fn custom_reporter_sees_each_diagnostic() {
+		struct Messages(Vec<String>);
+
+		impl Reporter for Messages {
+			fn report(&mut self, diagnostic: Diagnostic) {
+				self.0.push(format!("{}: {}", diagnostic.line, diagnostic.message));
+			}
+
+			fn had_error(&self) -> bool {
+				!self.0.is_empty()
+			}
+		}
+
+		let mut reporter = Messages(Vec::new());
+		run_reporting("print 1;\nprint @;\nvar;".to_owned(), &mut reporter);
+		assert_eq!(reporter.0, vec!["2: Unexpected character.", "2: Unexpected token", "3: Expected variable name, found ';' (Semicolon)"]);
+
+		let mut reporter = Messages(Vec::new());
+		run_reporting("print 1 / 0;".to_owned(), &mut reporter);
+		assert_eq!(reporter.0, vec!["1: Division by zero"]);
+	}
+
+	#[test]
+	fn imports_run_once_into_globals() {
+		let dir = std::env::temp_dir().join(format!("rlox-import-{}", std::process::id()));
+		std::fs::create_dir_all(dir.join("lib")).unwrap();
+		let main = "{ import \"lib/utils.lox\"; }\nimport \"lib/utils.lox\";\nprint twice(three);";
+		std::fs::write(dir.join("main.lox"), main).unwrap();
+		std::fs::write(dir.join("lib/utils.lox"), "import \"../main.lox\";\nimport \"more.lox\";\nfun twice(x) { return x * 2; }\nprint \"loaded\";").unwrap();
+		std::fs::write(dir.join("lib/more.lox"), "var three = 3;").unwrap();
+		std::fs::write(dir.join("lib/broken.lox"), "print ;").unwrap();
+
+		let options = RunOptions { script_path: Some(dir.join("main.lox")), ..RunOptions::default() };
+		let mut out = Vec::new();
+		let diagnostics = collect(main.to_owned(), Interpreter::with_output(Box::new(&mut out)), options.clone()).0;
+		assert!(diagnostics.is_empty());
+		assert_eq!(String::from_utf8(out).unwrap(), "loaded\n6\n");
+
+		let message = |src: &str| run_with_options(src.to_owned(), options.clone()).0[0].message.clone();
+		assert!(message("import \"missing.lox\";").starts_with(&format!("Could not read '{}'", dir.join("missing.lox").display())));
+		assert_eq!(message("import \"lib/broken.lox\";"), format!("Error in '{}' on line 1: Unexpected token", dir.join("lib/broken.lox").display()));
+
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,239 @@
+use crate::syntax::Stmt;
+use crate::syntax::StmtVisitor;
+use crate::syntax::ExprVisitor;
+use crate::syntax::Expr;
+use crate::syntax::FunctionDecl;
+use crate::tokens::Literal;
+use crate::tokens::Token;
+
+use std::rc::Rc;
+
+// Rough size and shape of a program. Function bodies and switch cases count
+// as blocks when measuring how deeply blocks nest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+	pub statements: usize,
+	pub expressions: usize,
+	pub max_depth: usize,
+	pub functions: usize
+}
+
+struct Counter {
+	metrics: Metrics,
+	depth: usize
+}
+
+impl Counter {
+	fn stmt(&mut self, stmt: &Stmt) {
+		self.metrics.statements += 1;
+		stmt.accept(self)
+	}
+
+	fn expr(&mut self, expr: &Expr) {
+		self.metrics.expressions += 1;
+		expr.accept(self)
+	}
+
+	fn exprs<'e>(&mut self, exprs: impl IntoIterator<Item = &'e Expr>) {
+		for expr in exprs {
+			self.expr(expr);
+		}
+	}
+
+	fn block(&mut self, stmts: &[Stmt]) {
+		self.depth += 1;
+		self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+		for stmt in stmts.iter() {
+			self.stmt(stmt);
+		}
+		self.depth -= 1;
+	}
+
+	fn function(&mut self, decl: &FunctionDecl) {
+		self.metrics.functions += 1;
+		self.block(&decl.body);
+	}
+}
+
+impl ExprVisitor<()> for &mut Counter {
+	fn visit_binary(self, left: &Expr, _op: &Token, right: &Expr) {
+		self.exprs(vec![left, right]);
+	}
+
+	fn visit_grouping(self, exp: &Expr) {
+		self.expr(exp);
+	}
+
+	fn visit_literal(self, _lit: &Literal) {}
+
+	fn visit_unary(self, _op: &Token, exp: &Expr) {
+		self.expr(exp);
+	}
+
+	fn visit_ternary(self, _op: &Token, left: &Expr, middle: &Expr, right: &Expr) {
+		self.exprs(vec![left, middle, right]);
+	}
+
+	fn visit_assignment(self, _name: &Token, value: &Expr) {
+		self.expr(value);
+	}
+
+	fn visit_postfix(self, _name: &Token, _op: &Token) {}
+
+	fn visit_variable_expr(self, _name: &Token) {}
+
+	fn visit_logical(self, left: &Expr, _op: &Token, right: &Expr) {
+		self.exprs(vec![left, right]);
+	}
+
+	fn visit_call(self, callee: &Expr, _paren: &Token, args: &[Expr]) {
+		self.expr(callee);
+		self.exprs(args);
+	}
+
+	fn visit_array(self, elements: &[Expr]) {
+		self.exprs(elements);
+	}
+
+	fn visit_index(self, object: &Expr, _bracket: &Token, index: &Expr) {
+		self.exprs(vec![object, index]);
+	}
+
+	fn visit_slice(self, object: &Expr, _bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) {
+		self.expr(object);
+		self.exprs(start.iter().chain(end.iter()).map(|xp| &**xp));
+	}
+
+	fn visit_map(self, _brace: &Token, entries: &[(Expr, Expr)]) {
+		self.exprs(entries.iter().flat_map(|(k, v)| vec![k, v]));
+	}
+
+	fn visit_set_index(self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) {
+		self.exprs(vec![object, index, value]);
+	}
+
+	fn visit_get(self, object: &Expr, _name: &Token) {
+		self.expr(object);
+	}
+
+	fn visit_set(self, object: &Expr, _name: &Token, value: &Expr) {
+		self.exprs(vec![object, value]);
+	}
+
+	fn visit_this(self, _keyword: &Token) {}
+
+	fn visit_super(self, _keyword: &Token, _method: &Token) {}
+}
+
+impl StmtVisitor<()> for &mut Counter {
+	fn visit_print(self, expr: &Expr) {
+		self.expr(expr);
+	}
+
+	fn visit_expr_statement(self, expr: &Expr) {
+		self.expr(expr);
+	}
+
+	fn visit_variable(self, _name: &Token, expr: &Option<Expr>) {
+		self.exprs(expr);
+	}
+
+	fn visit_global(self, _name: &Token, expr: &Option<Expr>) {
+		self.exprs(expr);
+	}
+
+	fn visit_block_stmt(self, stmts: &[Stmt]) {
+		self.block(stmts);
+	}
+
+	fn visit_if(self, cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) {
+		self.expr(cond);
+		self.stmt(then);
+		if let Some(otherwise) = otherwise {
+			self.stmt(otherwise);
+		}
+	}
+
+	fn visit_while(self, _label: &Option<Token>, cond: &Expr, then: &Stmt, increment: &Option<Box<Expr>>) {
+		self.expr(cond);
+		self.stmt(then);
+		self.exprs(increment.iter().map(|xp| &**xp));
+	}
+
+	fn visit_do_while(self, _label: &Option<Token>, body: &Stmt, cond: &Expr) {
+		self.stmt(body);
+		self.expr(cond);
+	}
+
+	fn visit_switch(self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) {
+		self.expr(subject);
+		for (value, stmts) in cases.iter() {
+			self.expr(value);
+			self.block(stmts);
+		}
+		if let Some(stmts) = default {
+			self.block(stmts);
+		}
+	}
+
+	fn visit_break(self, _line: usize, _label: &Option<Token>) {}
+
+	fn visit_continue(self, _line: usize, _label: &Option<Token>) {}
+
+	fn visit_empty(self, _line: usize) {}
+
+	fn visit_function(self, decl: &Rc<FunctionDecl>) {
+		self.function(decl);
+	}
+
+	fn visit_return(self, _keyword: &Token, value: &Option<Expr>) {
+		self.exprs(value);
+	}
+
+	fn visit_class(self, _name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) {
+		self.exprs(superclass);
+		for method in methods.iter() {
+			self.function(method);
+		}
+	}
+
+	fn visit_import(self, _keyword: &Token, _path: &str) {}
+}
+
+pub fn count(stmts: &[Stmt]) -> Metrics {
+	let mut counter = Counter {
+		metrics: Metrics::default(),
+		depth: 0
+	};
+
+	for stmt in stmts.iter() {
+		counter.stmt(stmt);
+	}
+	counter.metrics
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ErrorReporter;
+	use crate::parser::Parser;
+	use crate::tokens::Scanner;
+
+	fn measure(src: &str) -> Metrics {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		assert!(!err_rep.had_error);
+		count(&stmts)
+	}
+
+	#[test]
+	fn counts_a_small_program() {
+		assert_eq!(measure(""), Metrics::default());
+		assert_eq!(measure("print 1 + 2 * 3;"), Metrics { statements: 1, expressions: 5, max_depth: 0, functions: 0 });
+
+		let metrics = measure("fun f(n) { if (n < 2) { return n; } return f(n - 1); }
+			class A { m() { } } var x = f(3);");
+		assert_eq!(metrics, Metrics { statements: 7, expressions: 12, max_depth: 2, functions: 2 });
+	}
+}
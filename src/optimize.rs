@@ -0,0 +1,297 @@
+use crate::syntax::Stmt;
+use crate::syntax::StmtKind;
+use crate::syntax::StmtVisitor;
+use crate::syntax::ExprVisitor;
+use crate::syntax::Expr;
+use crate::syntax::ExprKind;
+use crate::syntax::FunctionDecl;
+use crate::tokens::Literal;
+use crate::tokens::Token;
+use crate::interpreter::Interpreter;
+
+use std::io;
+use std::rc::Rc;
+
+// Folds expressions whose operands are all literals into the literal they
+// evaluate to. Folding evaluates with a real interpreter, so folded values
+// match what running them would give; anything that would fail, like
+// `1 / 0`, is left in place to fail at runtime, and so is anything yielding
+// an infinity or NaN, which may fail there too.
+struct Optimizer<'a> {
+	interpreter: Interpreter<'a>
+}
+
+impl Optimizer<'_> {
+	fn fold(&mut self, expr: Expr) -> Expr {
+		let is_lit = |xp: &Expr| matches!(xp.kind, ExprKind::Literal(_));
+		let foldable = match &expr.kind {
+			ExprKind::Binary(left, _, right) | ExprKind::Logical(left, _, right) => is_lit(left) && is_lit(right),
+			ExprKind::Unary(_, exp) | ExprKind::Grouping(exp) => is_lit(exp),
+			ExprKind::Ternary(_, left, middle, right) => is_lit(left) && is_lit(middle) && is_lit(right),
+			_ => false
+		};
+
+		if !foldable {
+			return expr;
+		}
+
+		match expr.accept(&mut self.interpreter) {
+			Ok(Literal::Number(x)) if !x.is_finite() => expr,
+			Ok(lit) => Expr::new(ExprKind::Literal(lit), expr.span),
+			Err(_) => expr
+		}
+	}
+
+	// The rebuilt node keeps the span of the one it replaces, even once
+	// folded.
+	fn expr(&mut self, expr: &Expr) -> Expr {
+		let kind = expr.accept(&mut *self);
+		self.fold(Expr::new(kind, expr.span.clone()))
+	}
+
+	fn boxed(&mut self, expr: &Expr) -> Box<Expr> {
+		Box::new(self.expr(expr))
+	}
+
+	fn exprs(&mut self, exprs: &[Expr]) -> Vec<Expr> {
+		exprs.iter().map(|xp| self.expr(xp)).collect()
+	}
+
+	fn stmt(&mut self, stmt: &Stmt) -> Stmt {
+		Stmt::new(stmt.accept(&mut *self), stmt.span.clone())
+	}
+
+	fn stmts(&mut self, stmts: &[Stmt]) -> Vec<Stmt> {
+		stmts.iter().map(|stmt| self.stmt(stmt)).collect()
+	}
+
+	fn function(&mut self, decl: &FunctionDecl) -> Rc<FunctionDecl> {
+		Rc::new(FunctionDecl {
+			name: decl.name.clone(),
+			params: decl.params.clone(),
+			body: self.stmts(&decl.body),
+			span: decl.span.clone()
+		})
+	}
+}
+
+impl ExprVisitor<ExprKind> for &mut Optimizer<'_> {
+	fn visit_binary(self, left: &Expr, op: &Token, right: &Expr) -> ExprKind {
+		ExprKind::Binary(self.boxed(left), op.clone(), self.boxed(right))
+	}
+
+	fn visit_grouping(self, exp: &Expr) -> ExprKind {
+		ExprKind::Grouping(self.boxed(exp))
+	}
+
+	fn visit_literal(self, lit: &Literal) -> ExprKind {
+		ExprKind::Literal(lit.clone())
+	}
+
+	fn visit_unary(self, op: &Token, exp: &Expr) -> ExprKind {
+		ExprKind::Unary(op.clone(), self.boxed(exp))
+	}
+
+	fn visit_ternary(self, op: &Token, left: &Expr, middle: &Expr, right: &Expr) -> ExprKind {
+		ExprKind::Ternary(op.clone(), self.boxed(left), self.boxed(middle), self.boxed(right))
+	}
+
+	fn visit_assignment(self, name: &Token, value: &Expr) -> ExprKind {
+		ExprKind::Assignment(name.clone(), self.boxed(value))
+	}
+
+	fn visit_postfix(self, name: &Token, op: &Token) -> ExprKind {
+		ExprKind::Postfix(name.clone(), op.clone())
+	}
+
+	fn visit_variable_expr(self, name: &Token) -> ExprKind {
+		ExprKind::Var(name.clone())
+	}
+
+	fn visit_logical(self, left: &Expr, op: &Token, right: &Expr) -> ExprKind {
+		ExprKind::Logical(self.boxed(left), op.clone(), self.boxed(right))
+	}
+
+	fn visit_call(self, callee: &Expr, paren: &Token, args: &[Expr]) -> ExprKind {
+		ExprKind::Call(self.boxed(callee), paren.clone(), self.exprs(args))
+	}
+
+	fn visit_array(self, elements: &[Expr]) -> ExprKind {
+		ExprKind::Array(self.exprs(elements))
+	}
+
+	fn visit_index(self, object: &Expr, bracket: &Token, index: &Expr) -> ExprKind {
+		ExprKind::Index(self.boxed(object), bracket.clone(), self.boxed(index))
+	}
+
+	fn visit_slice(self, object: &Expr, bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) -> ExprKind {
+		let object = self.boxed(object);
+		let start = start.as_ref().map(|xp| self.boxed(xp));
+		let end = end.as_ref().map(|xp| self.boxed(xp));
+		ExprKind::Slice(object, bracket.clone(), start, end)
+	}
+
+	fn visit_map(self, brace: &Token, entries: &[(Expr, Expr)]) -> ExprKind {
+		let entries = entries.iter().map(|(k, v)| (self.expr(k), self.expr(v))).collect();
+		ExprKind::Map(brace.clone(), entries)
+	}
+
+	fn visit_set_index(self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> ExprKind {
+		ExprKind::SetIndex(self.boxed(object), bracket.clone(), self.boxed(index), self.boxed(value))
+	}
+
+	fn visit_get(self, object: &Expr, name: &Token) -> ExprKind {
+		ExprKind::Get(self.boxed(object), name.clone())
+	}
+
+	fn visit_set(self, object: &Expr, name: &Token, value: &Expr) -> ExprKind {
+		ExprKind::Set(self.boxed(object), name.clone(), self.boxed(value))
+	}
+
+	fn visit_this(self, keyword: &Token) -> ExprKind {
+		ExprKind::This(keyword.clone())
+	}
+
+	fn visit_super(self, keyword: &Token, method: &Token) -> ExprKind {
+		ExprKind::Super(keyword.clone(), method.clone())
+	}
+}
+
+impl StmtVisitor<StmtKind> for &mut Optimizer<'_> {
+	fn visit_print(self, expr: &Expr) -> StmtKind {
+		StmtKind::Print(self.expr(expr))
+	}
+
+	fn visit_expr_statement(self, expr: &Expr) -> StmtKind {
+		StmtKind::Expr(self.expr(expr))
+	}
+
+	fn visit_variable(self, name: &Token, expr: &Option<Expr>) -> StmtKind {
+		StmtKind::Var(name.clone(), expr.as_ref().map(|xp| self.expr(xp)))
+	}
+
+	fn visit_global(self, name: &Token, expr: &Option<Expr>) -> StmtKind {
+		StmtKind::Global(name.clone(), expr.as_ref().map(|xp| self.expr(xp)))
+	}
+
+	fn visit_block_stmt(self, stmts: &[Stmt]) -> StmtKind {
+		StmtKind::Block(self.stmts(stmts))
+	}
+
+	fn visit_if(self, cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) -> StmtKind {
+		let cond = self.boxed(cond);
+		let then = Box::new(self.stmt(then));
+		let otherwise = Box::new(otherwise.as_ref().map(|stmt| self.stmt(stmt)));
+		StmtKind::If(cond, then, otherwise)
+	}
+
+	fn visit_while(self, label: &Option<Token>, cond: &Expr, then: &Stmt, increment: &Option<Box<Expr>>) -> StmtKind {
+		let cond = self.boxed(cond);
+		let then = Box::new(self.stmt(then));
+		let increment = increment.as_ref().map(|xp| self.boxed(xp));
+		StmtKind::While(label.clone(), cond, then, increment)
+	}
+
+	fn visit_do_while(self, label: &Option<Token>, body: &Stmt, cond: &Expr) -> StmtKind {
+		let body = Box::new(self.stmt(body));
+		StmtKind::DoWhile(label.clone(), body, self.boxed(cond))
+	}
+
+	fn visit_switch(self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) -> StmtKind {
+		let subject = self.expr(subject);
+		let cases = cases.iter().map(|(value, stmts)| (self.expr(value), self.stmts(stmts))).collect();
+		let default = default.as_ref().map(|stmts| self.stmts(stmts));
+		StmtKind::Switch(subject, cases, default)
+	}
+
+	fn visit_break(self, line: usize, label: &Option<Token>) -> StmtKind {
+		StmtKind::Break(line, label.clone())
+	}
+
+	fn visit_continue(self, line: usize, label: &Option<Token>) -> StmtKind {
+		StmtKind::Continue(line, label.clone())
+	}
+
+	fn visit_empty(self, line: usize) -> StmtKind {
+		StmtKind::Empty(line)
+	}
+
+	fn visit_function(self, decl: &Rc<FunctionDecl>) -> StmtKind {
+		StmtKind::Function(self.function(decl))
+	}
+
+	fn visit_return(self, keyword: &Token, value: &Option<Expr>) -> StmtKind {
+		StmtKind::Return(keyword.clone(), value.as_ref().map(|xp| self.expr(xp)))
+	}
+
+	fn visit_class(self, name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) -> StmtKind {
+		let superclass = superclass.as_ref().map(|xp| self.expr(xp));
+		let methods = methods.iter().map(|method| self.function(method)).collect();
+		StmtKind::Class(name.clone(), superclass, methods)
+	}
+
+	fn visit_import(self, keyword: &Token, path: &str) -> StmtKind {
+		StmtKind::Import(keyword.clone(), path.to_owned())
+	}
+}
+
+// Folding follows the rules the program will run under, so strict_plus
+// should match the interpreter's.
+pub fn optimize(stmts: &[Stmt], strict_plus: bool) -> Vec<Stmt> {
+	let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+	interpreter.set_strict_plus(strict_plus);
+	let mut optimizer = Optimizer { interpreter };
+
+	optimizer.stmts(stmts)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ErrorReporter;
+	use crate::interpreter;
+	use crate::parser::Parser;
+	use crate::syntax::PrettyPrint;
+	use crate::tokens::Scanner;
+
+	fn parse(src: &str) -> Vec<Stmt> {
+		let mut err_rep = ErrorReporter::new();
+		let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+		let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+		assert!(!err_rep.had_error);
+		stmts
+	}
+
+	fn print(stmts: &[Stmt]) -> Vec<String> {
+		stmts.iter().map(|stmt| stmt.accept(&PrettyPrint)).collect()
+	}
+
+	fn output(stmts: &[Stmt]) -> String {
+		let mut out = Vec::new();
+		let res = interpreter::interpret(&mut Interpreter::with_output(Box::new(&mut out)), stmts);
+		assert!(res.is_ok());
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn folds_constant_expressions() {
+		let stmts = parse("print 2 + 3 * 4; print true and false; print !(1 < 2) ? \"a\" : \"b\" + \"c\"; print -(~0);");
+		assert_eq!(print(&optimize(&stmts, false)), vec!["(print 14)", "(print false)", "(print \"bc\")", "(print 1)"]);
+	}
+
+	#[test]
+	fn leaves_variables_and_errors_alone() {
+		let stmts = parse("var a = 1; print a + 2 * 3; print 1 / 0; fun f() { return 1 + 1; }");
+		assert_eq!(print(&optimize(&stmts, false)), vec!["(var a 1)", "(print (+ a 6))", "(print (/ 1 0))", "(fun f () (return 2))"]);
+		assert_eq!(print(&optimize(&parse("print 1 + \"x\";"), true)), vec!["(print (+ 1 \"x\"))"]);
+		assert_eq!(print(&optimize(&parse("if (false) print \"x\" * 10000000000000000000;"), false)), vec!["(if false (print (* \"x\" 10000000000000000000)))"]);
+	}
+
+	#[test]
+	fn folding_keeps_semantics() {
+		let src = "var a = 2; print (1 + 2) * a; print 10 / 4 - 1; print \"n\" + 1 + 2; print nil or \"x\";
+			fun f(n) { return n * (3 - 1); } print f(4); if (1 < 2 and 2 < 3) print \"yes\";";
+		let stmts = parse(src);
+		assert_eq!(output(&stmts), output(&optimize(&stmts, false)));
+	}
+}
@@ -1,54 +1,108 @@
 use crate::syntax::Stmt;
+use crate::syntax::StmtKind;
 use crate::syntax::Expr;
+use crate::syntax::ExprKind;
+use crate::syntax::FunctionDecl;
 use crate::tokens::Literal;
 use crate::tokens::Token;
 use crate::tokens::TokenType;
-use crate::ErrorReporter;
-use std::collections::VecDeque;
+use crate::Reporter;
+use std::ops::Range;
+use std::rc::Rc;
 
 pub struct Parser<'a> {
-    tokens: VecDeque<Token>,
-    previous: Option<Token>,
-    err_rep: &'a mut ErrorReporter,
+    tokens: Vec<Token>,
+    // Index of the next token to consume. Saving and restoring it is enough
+    // to backtrack.
+    current: usize,
+    // None lifts the limit on how many arguments a call may pass, and so how
+    // many parameters a function may take.
+    max_args: Option<usize>,
+    err_rep: &'a mut dyn Reporter,
 }
 
-pub struct ParserError;
+// The argument limit the reference implementation uses.
+pub const MAX_ARGS: usize = 255;
+
+// UnexpectedEof marks input that ran out mid-statement, which an interactive
+// prompt can complete by reading more lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParserError {
+    Syntax,
+    UnexpectedEof,
+}
 
 type Result<T> = std::result::Result<T, ParserError>;
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: Vec<Token>, err_rep: &mut ErrorReporter) -> Parser {
+    pub fn new(tokens: Vec<Token>, err_rep: &'a mut dyn Reporter) -> Parser<'a> {
         Parser {
-            tokens: VecDeque::from(tokens),
-            previous: None,
+            tokens,
+            current: 0,
+            max_args: Some(MAX_ARGS),
             err_rep,
         }
     }
 
+    pub fn with_max_args(mut self, max_args: Option<usize>) -> Parser<'a> {
+        self.max_args = max_args;
+        self
+    }
+
+    // Keeps parsing after an error so every error gets reported, then fails
+    // with the first one.
     pub fn parse(&mut self) -> Result<Vec<Stmt>> {
         let mut stmts = Vec::new();
-        while !self.tokens.is_empty() && !self.curr_match(&vec![TokenType::Eof]){
-        	stmts.push(self.declaration()?);
+        let mut first_err = None;
+
+        while !self.is_at_end() && !self.curr_match(&vec![TokenType::Eof]){
+            match self.declaration() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => first_err = first_err.or(Some(err)),
+            }
         }
 
-        Ok(stmts)
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(stmts),
+        }
+    }
+
+    // Parses input holding a single expression and nothing after it.
+    pub fn parse_expression(&mut self) -> Result<Expr> {
+        let expr = self.expression()?;
+
+        if !self.check(&TokenType::Eof) {
+            let tk = self.peek_or_end();
+            return Err(self.error(&tk, "Expected end of expression"));
+        }
+
+        Ok(expr)
     }
 
     fn declaration(&mut self) -> Result<Stmt> {
+    	let start = self.next_offset();
     	let res = if self.curr_match(&vec![TokenType::Var]) {
     		self.var_declaration()
+    	} else if self.curr_match(&vec![TokenType::Global]) {
+    		self.global_declaration()
+    	} else if self.curr_match(&vec![TokenType::Fun]) {
+    		self.function("Expected function name.").map(StmtKind::Function)
+    	} else if self.curr_match(&vec![TokenType::Class]) {
+    		self.class_declaration()
     	} else {
-    		self.statement()
+    		self.statement_kind()
     	};
+    	let res = res.map(|kind| Stmt::new(kind, self.span_from(start)));
 
-    	if let Err(_) = res {
+    	if res.is_err() {
     		self.synchronize();
     	}
 
     	res
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt> {
+    fn var_declaration(&mut self) -> Result<StmtKind> {
     	let name = self.consume(TokenType::Identifier, "Expected variable name.")?;
 
     	let mut init = None;
@@ -57,12 +111,82 @@ impl<'a> Parser<'a> {
     	}
 
     	self.consume(TokenType::Semicolon, "Expected ';' after the variable declaration")?;
-    	Ok(Stmt::Var(name,init))
+    	Ok(StmtKind::Var(name,init))
     }
 
+    fn function(&mut self, name_msg: &'static str) -> Result<Rc<FunctionDecl>> {
+        let start = self.next_offset();
+        let name = self.consume(TokenType::Identifier, name_msg)?;
+        self.consume(TokenType::LeftParen, "Expected '(' after name.")?;
+
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if Some(params.len()) == self.max_args {
+                    let tk = self.peek_or_end();
+                    let msg = format!("Cannot have more than {} parameters.", params.len());
+                    self.error(&tk, &msg);
+                }
 
-    fn for_statement(&mut self) -> Result<Stmt> {
+                params.push(self.consume(TokenType::Identifier, "Expected parameter name.")?);
+                if !self.curr_match(&vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expected ')' after parameters.")?;
+        self.consume(TokenType::LeftBrace, "Expected '{' before body.")?;
+        let body = self.block_statements()?;
+
+        Ok(Rc::new(FunctionDecl {
+            name,
+            params,
+            body,
+            span: self.span_from(start)
+        }))
+    }
+
+    fn class_declaration(&mut self) -> Result<StmtKind> {
+        let name = self.consume(TokenType::Identifier, "Expected class name.")?;
+
+        let superclass = if self.curr_match(&vec![TokenType::Less]) {
+            let superclass = self.consume(TokenType::Identifier, "Expected superclass name.")?;
+            Some(Expr::new(ExprKind::Var(superclass.clone()), superclass.get_span()))
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expected '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.check(&TokenType::Eof) {
+            methods.push(self.function("Expected method name.")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after class body.")?;
+        Ok(StmtKind::Class(name, superclass, methods))
+    }
+
+    fn global_declaration(&mut self) -> Result<StmtKind> {
+    	let name = self.consume(TokenType::Identifier, "Expected variable name.")?;
+
+    	let mut init = None;
+    	if self.curr_match(&vec![TokenType::Equal]) {
+    		init = Some(self.expression()?);
+    	}
+
+    	self.consume(TokenType::Semicolon, "Expected ';' after the global declaration")?;
+    	Ok(StmtKind::Global(name,init))
+    }
+
+    // The loop without its initializer spans from `for` to the end of the
+    // body, as does a condition left out, which is an empty span where it
+    // would be.
+    fn for_statement(&mut self, label: Option<Token>) -> Result<StmtKind> {
+        let start = self.previous().map_or(0, |tk| tk.get_span().start);
         self.consume(TokenType::LeftParen, "Expected '(' after 'for'.")?;
+        let init_start = self.next_offset();
         let init = if self.curr_match(&vec![TokenType::Semicolon]) {
             None
         } else if self.curr_match(&vec![TokenType::Var]) {
@@ -70,7 +194,9 @@ impl<'a> Parser<'a> {
         } else {
             Some(self.expression_statement()?)
         };
+        let init = init.map(|kind| Stmt::new(kind, self.span_from(init_start)));
 
+        let cond_start = self.next_offset();
         let cond = if !self.check(&TokenType::Semicolon) {
             Some(self.expression()?)
         } else {
@@ -87,70 +213,167 @@ impl<'a> Parser<'a> {
 
         self.consume(TokenType::RightParen, "Expected ')' after for clauses.")?;
 
-        let body = self.statement()?;
-
-        let body = if let Some(increment) = increment {
-            Stmt::Block(vec![body,Stmt::Expr(increment)])
-        } else {
-            body
-        };
-
-        let cond = Box::new(cond.unwrap_or(Expr::Literal(Literal::Boolean(true))));
-        let body = Box::new(body);
-
-        let wstmt = Stmt::While(cond,body);
+        let body = Box::new(self.statement()?);
+        let cond = Box::new(cond.unwrap_or(Expr::new(ExprKind::Literal(Literal::Boolean(true)), cond_start..cond_start)));
+        let wstmt = Stmt::new(StmtKind::While(label, cond, body, increment.map(Box::new)), self.span_from(start));
 
         let full_stmt = if let Some(init) = init {
-            Stmt::Block(vec![init,wstmt])
+            StmtKind::Block(vec![init,wstmt])
         } else {
-            wstmt
+            wstmt.kind
         };
 
         Ok(full_stmt)
     }
 
     fn statement(&mut self) -> Result<Stmt> {
-    	if self.curr_match(&vec![TokenType::Print]) {
-    		return self.print_statement()
-    	} else if self.curr_match(&vec![TokenType::LeftBrace]) {
-            return self.block()
+        let start = self.next_offset();
+        let kind = self.statement_kind()?;
+        Ok(Stmt::new(kind, self.span_from(start)))
+    }
+
+    fn statement_kind(&mut self) -> Result<StmtKind> {
+    	if self.curr_match(&vec![TokenType::Semicolon]) {
+            Ok(StmtKind::Empty(self.previous().unwrap().get_line()))
+        } else if self.curr_match(&vec![TokenType::Print]) {
+    		self.print_statement()
+    	} else if !self.is_map_literal() && self.curr_match(&vec![TokenType::LeftBrace]) {
+            self.block()
         } else if self.curr_match(&vec![TokenType::If]) {
-            return self.if_statement()
+            self.if_statement()
+        } else if self.check(&TokenType::Identifier) && self.check_nth(1, &TokenType::Colon) {
+            self.labelled_loop()
         } else if self.curr_match(&vec![TokenType::While]) {
-            self.while_statement()
+            self.while_statement(None)
         } else if self.curr_match(&vec![TokenType::For]) {
-            self.for_statement()
+            self.for_statement(None)
         } else if self.curr_match(&vec![TokenType::Break]) {
             self.break_statement()
+        } else if self.curr_match(&vec![TokenType::Continue]) {
+            self.continue_statement()
+        } else if self.curr_match(&vec![TokenType::Switch]) {
+            self.switch_statement()
+        } else if self.curr_match(&vec![TokenType::Do]) {
+            self.do_while_statement(None)
+        } else if self.curr_match(&vec![TokenType::Return]) {
+            self.return_statement()
+        } else if self.curr_match(&vec![TokenType::Import]) {
+            self.import_statement()
         }
          else {
-    		return self.expression_statement()
+    		self.expression_statement()
     	}
     }
 
 
-    fn break_statement(&mut self) -> Result<Stmt> {
+    // A label names the loop right after it, so break and continue can
+    // target it from inside nested loops.
+    fn labelled_loop(&mut self) -> Result<StmtKind> {
+        let label = self.advance();
+        self.advance();
+
+        if self.curr_match(&vec![TokenType::While]) {
+            self.while_statement(label)
+        } else if self.curr_match(&vec![TokenType::For]) {
+            self.for_statement(label)
+        } else if self.curr_match(&vec![TokenType::Do]) {
+            self.do_while_statement(label)
+        } else {
+            let tk = self.peek_or_end();
+            Err(self.error(&tk, &expected_found("Expected a loop after label", &tk)))
+        }
+    }
+
+    fn break_statement(&mut self) -> Result<StmtKind> {
         let line = self.previous().unwrap().get_line();
+        let label = self.loop_label();
         self.consume(TokenType::Semicolon, "Expected ';' after break.")?;
-        Ok(Stmt::Break(line))
+        Ok(StmtKind::Break(line, label))
+    }
+
+    fn continue_statement(&mut self) -> Result<StmtKind> {
+        let line = self.previous().unwrap().get_line();
+        let label = self.loop_label();
+        self.consume(TokenType::Semicolon, "Expected ';' after continue.")?;
+        Ok(StmtKind::Continue(line, label))
+    }
+
+    fn loop_label(&mut self) -> Option<Token> {
+        if self.curr_match(&vec![TokenType::Identifier]) {
+            self.previous()
+        } else {
+            None
+        }
+    }
+
+    fn switch_statement(&mut self) -> Result<StmtKind> {
+        self.consume(TokenType::LeftParen, "Expected '(' after switch")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after switch value")?;
+        self.consume(TokenType::LeftBrace, "Expected '{' before switch cases")?;
+
+        let mut cases = Vec::new();
+        let mut default = None;
+
+        while !self.check(&TokenType::RightBrace) && !self.check(&TokenType::Eof) {
+            if self.curr_match(&vec![TokenType::Case]) {
+                let value = self.expression()?;
+                self.consume(TokenType::Colon, "Expected ':' after case value")?;
+                cases.push((value, self.case_body()?));
+            } else if self.curr_match(&vec![TokenType::Default]) {
+                let tk = self.previous().unwrap();
+                self.consume(TokenType::Colon, "Expected ':' after default")?;
+                if default.is_some() {
+                    self.error(&tk, "Switch can only have one default case.");
+                }
+                default = Some(self.case_body()?);
+            } else {
+                let tk = self.peek_or_end();
+                return Err(self.error(&tk, "Expected 'case' or 'default' in switch"));
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after switch cases")?;
+        Ok(StmtKind::Switch(subject, cases, default))
     }
 
-    fn while_statement(&mut self) -> Result<Stmt> {
+    fn case_body(&mut self) -> Result<Vec<Stmt>> {
+        let mut statements = Vec::new();
+
+        while !self.check(&TokenType::Case) && !self.check(&TokenType::Default)
+            && !self.check(&TokenType::RightBrace) && !self.check(&TokenType::Eof) {
+            statements.push(self.declaration()?);
+        }
+
+        Ok(statements)
+    }
+
+    fn do_while_statement(&mut self, label: Option<Token>) -> Result<StmtKind> {
+        let body = Box::new(self.statement()?);
+        self.consume(TokenType::While, "Expected 'while' after do body")?;
+        self.consume(TokenType::LeftParen, "Expected '(' after while")?;
+        let cond = Box::new(self.expression()?);
+        self.consume(TokenType::RightParen, "Expected ')' after condition")?;
+        self.consume(TokenType::Semicolon, "Expected ';' after do while loop")?;
+        Ok(StmtKind::DoWhile(label, body, cond))
+    }
+
+    fn while_statement(&mut self, label: Option<Token>) -> Result<StmtKind> {
         self.consume(TokenType::LeftParen, "Expected '(' after while")?;
         let cond = Box::new(self.expression()?);
         self.consume(TokenType::RightParen, "Expected ')' after condition")?;
         let then = Box::new(self.statement()?);
-        Ok(Stmt::While(cond,then))
+        Ok(StmtKind::While(label, cond, then, None))
     }
 
 
-    fn if_statement(&mut self) -> Result<Stmt> {
+    fn if_statement(&mut self) -> Result<StmtKind> {
         self.consume(TokenType::LeftParen, "Expected '(' after if")?;
         let cond = Box::new(self.expression()?);
         self.consume(TokenType::RightParen, "Expected ')' after condition")?;
         let then = Box::new(self.statement()?);
 
-        Ok(Stmt::If(cond,then, Box::new(if self.curr_match(&vec![TokenType::Else]) {
+        Ok(StmtKind::If(cond,then, Box::new(if self.curr_match(&vec![TokenType::Else]) {
             let otherwise = self.statement()?;
             Some(otherwise)
         } else {
@@ -160,52 +383,116 @@ impl<'a> Parser<'a> {
     }
 
 
-    fn block(&mut self) -> Result<Stmt> {
+    fn block(&mut self) -> Result<StmtKind> {
+        Ok(StmtKind::Block(self.block_statements()?))
+    }
+
+    fn block_statements(&mut self) -> Result<Vec<Stmt>> {
         let mut statements = Vec::new();
 
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
             statements.push(self.declaration()?)
         }
         self.consume(TokenType::RightBrace, "Expected '}' ater block.")?;
-        Ok(Stmt::Block(statements))
+        Ok(statements)
+    }
+
+    fn return_statement(&mut self) -> Result<StmtKind> {
+        let keyword = self.previous().unwrap();
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenType::Semicolon, "Expected ';' after return value.")?;
+        Ok(StmtKind::Return(keyword, value))
     }
 
+    fn import_statement(&mut self) -> Result<StmtKind> {
+        let keyword = self.previous().unwrap();
+        let path = match self.peek().map(|tk| tk.get_type()) {
+            Some(TokenType::Literal(Literal::String(path))) => path.clone(),
+            _ => {
+                let tk = self.peek_or_end();
+                return Err(self.error(&tk, &expected_found("Expected a file path after 'import'", &tk)));
+            }
+        };
+        self.advance();
+
+        self.consume(TokenType::Semicolon, "Expected ';' after import path.")?;
+        Ok(StmtKind::Import(keyword, path))
+    }
 
     fn is_at_end(&self) -> bool {
-        if let Some(_) = self.peek() {
-            false
-        } else {
-            true
-        }
+        self.peek().is_none()
     }
-    fn print_statement(&mut self) -> Result<Stmt> {
+    fn print_statement(&mut self) -> Result<StmtKind> {
     	let value = self.expression()?;
-    	self.consume(TokenType::Semicolon, "Expected ';' after value")?;
-    	Ok(Stmt::Print(value))
+    	self.end_statement()?;
+    	Ok(StmtKind::Print(value))
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt> {
+    fn expression_statement(&mut self) -> Result<StmtKind> {
     	let value = self.expression()?;
-    	self.consume(TokenType::Semicolon, "Expected ';' after value")?;
-    	Ok(Stmt::Expr(value))
+    	self.end_statement()?;
+    	Ok(StmtKind::Expr(value))
+    }
+
+    // A missing ';' is reported but forgiven when the next token starts a new
+    // line, as the statement most likely ended there. Anywhere else, or at the
+    // end of input, it is an error as usual.
+    fn end_statement(&mut self) -> Result<()> {
+        if self.check(&TokenType::Semicolon) {
+            self.advance();
+            return Ok(());
+        }
+
+        let next = self.peek_or_end();
+        let last_line = self.previous().map_or(0, |tk| tk.get_line());
+        let msg = expected_found("Expected ';' after value", &next);
+        if !matches!(next.get_type(), TokenType::Eof) && next.get_line() > last_line {
+            self.error(&next, &msg);
+            Ok(())
+        } else {
+            Err(self.error(&next, &msg))
+        }
     }
 
     fn expression(&mut self) -> Result<Expr> {
-        return self.assignment();
+        self.comma()
     }
 
     fn assignment(&mut self) -> Result<Expr> {
-        let expr = self.comma()?;
+        let expr = self.ternary()?;
 
-        if self.curr_match(&vec![TokenType::Equal]) {
+        if self.curr_match(&vec![TokenType::Equal, TokenType::PlusEqual, TokenType::MinusEqual,
+            TokenType::StarEqual, TokenType::SlashEqual]) {
             let equals = self.previous().unwrap();
             let value = self.assignment()?;
-
-            if let Expr::Var(nm) = expr {
-                let lval = Expr::Assignment(nm,Box::new(value));
+            let span = join(&expr, &value);
+
+            if let ExprKind::Var(nm) = expr.kind {
+                let value = match compound_operator(&equals) {
+                    Some(op) => Expr::new(ExprKind::Binary(Box::new(Expr::new(ExprKind::Var(nm.clone()), expr.span)), op, Box::new(value)), span.clone()),
+                    None => value,
+                };
+                let lval = Expr::new(ExprKind::Assignment(nm,Box::new(value)), span);
                 return Ok(lval);
             }
 
+            // Compound assignment would evaluate the indexed object twice, so
+            // only plain assignment is allowed on an index.
+            if let TokenType::Equal = equals.get_type() {
+                if let ExprKind::Index(object, bracket, index) = expr.kind {
+                    return Ok(Expr::new(ExprKind::SetIndex(object, bracket, index, Box::new(value)), span));
+                }
+
+                if let ExprKind::Get(object, name) = expr.kind {
+                    return Ok(Expr::new(ExprKind::Set(object, name, Box::new(value)), span));
+                }
+            }
+
             self.error(&equals, "Invalid assignment target.");
         }
         
@@ -256,28 +543,97 @@ impl<'a> Parser<'a> {
     }
 
     fn peek(&self) -> Option<&Token> {
-        self.tokens.front()
+        self.tokens.get(self.current)
+    }
+
+    // The next token, or an Eof placed after the last token when a caller
+    // handed over tokens without the Eof the scanner ends with.
+    fn peek_or_end(&self) -> Token {
+        match self.peek() {
+            Some(tk) => tk.clone(),
+            None => {
+                let (line, column) = self.tokens.last()
+                    .map_or((1, 1), |tk| (tk.get_line(), tk.get_column() + tk.get_lexeme().chars().count()));
+                Token::new(TokenType::Eof, String::new(), line, column)
+            }
+        }
+    }
+
+    fn peek_next(&self) -> Option<&Token> {
+        self.tokens.get(self.current + 1)
+    }
+
+    // A '{' opening a statement is a block unless it is followed by a
+    // string or number key and a ':'.
+    fn is_map_literal(&self) -> bool {
+        let key = self.peek_next().map(|tk| tk.get_type());
+        self.check_nth(0, &TokenType::LeftBrace)
+            && matches!(key, Some(TokenType::Literal(Literal::String(_))) | Some(TokenType::Literal(Literal::Number(_))))
+            && self.check_nth(2, &TokenType::Colon)
+    }
+
+    fn check_nth(&self, n: usize, ty: &TokenType) -> bool {
+        self.tokens.get(self.current + n).is_some_and(|tk| tk.get_type() == ty)
     }
 
     fn advance(&mut self) -> Option<Token> {
-        self.previous = self.tokens.pop_front();
-        self.previous.clone()
+        let tk = self.peek().cloned();
+        if tk.is_some() {
+            self.current += 1;
+        }
+        tk
+    }
+
+    fn previous(&self) -> Option<Token> {
+        self.current.checked_sub(1).and_then(|i| self.tokens.get(i)).cloned()
+    }
+
+    // Where the next token starts, and so where a node parsed from here on
+    // starts.
+    fn next_offset(&self) -> usize {
+        match self.peek() {
+            Some(tk) => tk.get_span().start,
+            None => self.previous().map_or(0, |tk| tk.get_span().end)
+        }
     }
 
-    fn previous(&mut self) -> Option<Token> {
-        self.previous.take()
+    // From the given offset to the end of the last token consumed.
+    fn span_from(&self, start: usize) -> Range<usize> {
+        let end = self.previous().map_or(start, |tk| tk.get_span().end);
+        start..end.max(start)
     }
 
+    // `1 < 2 < 3` would compare a boolean with a number, which always fails
+    // at runtime, so a chain is reported here instead. Parsing carries on as
+    // if it were grouped to the left.
     fn comparison(&mut self) -> Result<Expr> {
-        self.match_left_asoc(
-            vec![
-                TokenType::Less,
-                TokenType::LessEqual,
-                TokenType::Greater,
-                TokenType::GreaterEqual,
-            ],
-            |x| x.addition(),
-        )
+        let comparisons = vec![
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+        ];
+        let mut expr = self.shift()?;
+
+        let mut chained = false;
+        while self.curr_match(&comparisons) {
+            let op = self.previous().unwrap();
+            if chained {
+                self.error(&op, "Chained comparison is not allowed; use explicit parentheses or 'and'.");
+            }
+            chained = true;
+
+            let right = self.shift()?;
+            let span = join(&expr, &right);
+            expr = Expr::new(ExprKind::Binary(Box::new(expr), op, Box::new(right)), span);
+        }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr> {
+        self.match_left_asoc(vec![TokenType::LessLess, TokenType::GreaterGreater], |x| {
+            x.addition()
+        })
     }
 
     fn addition(&mut self) -> Result<Expr> {
@@ -290,13 +646,32 @@ impl<'a> Parser<'a> {
         self.match_left_asoc(vec![TokenType::Star, TokenType::Slash], |x| x.unary())
     }
 
+    // `++x` is `x += 1`, and `--x` is `x -= 1`.
     fn unary(&mut self) -> Result<Expr> {
-        if self.curr_match(&vec![TokenType::Bang, TokenType::Minus]) {
+        if self.curr_match(&vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let op = self.previous().unwrap();
+            let target = self.unary()?;
+            let span = op.get_span().start..target.span.end;
+            match target.kind {
+                ExprKind::Var(nm) => {
+                    let one = Expr::new(ExprKind::Literal(Literal::Number(1.0)), op.get_span());
+                    let step = Expr::new(ExprKind::Binary(Box::new(Expr::new(ExprKind::Var(nm.clone()), target.span)),
+                        compound_operator(&op).unwrap(), Box::new(one)), span.clone());
+                    Ok(Expr::new(ExprKind::Assignment(nm, Box::new(step)), span))
+                },
+                kind => {
+                    self.error(&op, "Invalid increment target.");
+                    Ok(Expr::new(kind, target.span))
+                }
+            }
+        } else if self.curr_match(&vec![TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let op = self.previous().unwrap();
             let right = self.unary()?;
-            Ok(Expr::Unary(op, Box::new(right)))
+            let span = op.get_span().start..right.span.end;
+            Ok(Expr::new(ExprKind::Unary(op, Box::new(right)), span))
         } else if self.curr_match(&vec![TokenType::EqualEqual,TokenType::BangEqual,TokenType::Plus,TokenType::Minus,
-        	TokenType::LessEqual, TokenType::Less, TokenType::GreaterEqual, TokenType::Greater, TokenType::Star, TokenType::Slash]){
+        	TokenType::LessEqual, TokenType::Less, TokenType::GreaterEqual, TokenType::Greater, TokenType::Star, TokenType::Slash,
+        	TokenType::Ampersand, TokenType::Pipe, TokenType::Caret, TokenType::LessLess, TokenType::GreaterGreater]){
         	let op = self.previous().unwrap();
         	self.error(&op, "Expression expected before binary operator");
             let right = self.unary()?;
@@ -307,17 +682,59 @@ impl<'a> Parser<'a> {
     }
 
     fn call(&mut self) -> Result<Expr> {
-        let mut expr = self.primary();
+        let mut expr = self.primary()?;
 
-        while true {
+        loop {
             if self.curr_match(&vec![TokenType::LeftParen]) {
-                expr = self.finish_call(expr);
+                expr = self.finish_call(expr)?;
+            } else if self.curr_match(&vec![TokenType::LeftBracket]) {
+                expr = self.finish_index(expr)?;
+            } else if self.curr_match(&vec![TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expected property name after '.'.")?;
+                let span = expr.span.start..name.get_span().end;
+                expr = Expr::new(ExprKind::Get(Box::new(expr), name), span);
+            } else if self.curr_match(&vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+                let op = self.previous().unwrap();
+                let span = expr.span.start..op.get_span().end;
+                expr = match expr.kind {
+                    ExprKind::Var(nm) => Expr::new(ExprKind::Postfix(nm, op), span),
+                    kind => {
+                        self.error(&op, "Invalid increment target.");
+                        Expr::new(kind, expr.span)
+                    }
+                };
             } else {
                 break;
             }
         }
 
-        expr
+        Ok(expr)
+    }
+
+    fn finish_index(&mut self, expr: Expr) -> Result<Expr> {
+        let bracket = self.previous().unwrap();
+
+        let start = if self.check(&TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.ternary()?))
+        };
+
+        if self.curr_match(&vec![TokenType::Colon]) {
+            let end = if self.check(&TokenType::RightBracket) {
+                None
+            } else {
+                Some(Box::new(self.ternary()?))
+            };
+
+            self.consume(TokenType::RightBracket, "Expected ']' after slice")?;
+            let span = self.span_from(expr.span.start);
+            return Ok(Expr::new(ExprKind::Slice(Box::new(expr), bracket, start, end), span));
+        }
+
+        self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+        let span = self.span_from(expr.span.start);
+        Ok(Expr::new(ExprKind::Index(Box::new(expr), bracket, start.unwrap()), span))
     }
 
     fn finish_call(&mut self, expr: Expr) -> Result<Expr> {
@@ -325,60 +742,118 @@ impl<'a> Parser<'a> {
 
         if !self.check(&TokenType::RightParen) {
             loop {
-
-                 if args.len() >= 8 {
-                    self.error(self.peek().unwrap(), "Cannot have more than 8 arguments.");
+                // Reported once, at the first extra argument, and parsing
+                // carries on since the call itself is well formed.
+                if Some(args.len()) == self.max_args {
+                    let tk = self.peek_or_end();
+                    let msg = format!("Cannot have more than {} arguments.", args.len());
+                    self.error(&tk, &msg);
                 }
 
-                args.push(self.expression()?);
-                if !self.curr_match(&vec![TokenType::Comma]) {
+                // Commas separate arguments here, so skip the comma operator.
+                args.push(self.assignment()?);
+                if !self.curr_match(&vec![TokenType::Comma]) || self.check(&TokenType::RightParen) {
                     break;
                 }
             }
         }
 
         let token = self.consume(TokenType::RightParen, "Expected ')' after arguments.")?;
-        Ok(Expr::Call(Box::new(expr), token, args))
+        let span = self.span_from(expr.span.start);
+        Ok(Expr::new(ExprKind::Call(Box::new(expr), token, args), span))
     }
 
     fn primary(&mut self) -> Result<Expr> {
+        let start = self.next_offset();
+
         if self.is_literal() {
-            if let TokenType::Literal(lt) = self.previous().unwrap().get_type().clone() {
-                return Ok(Expr::Literal(lt));
+            let tk = self.previous().unwrap();
+            if let TokenType::Literal(lt) = tk.get_type().clone() {
+                return Ok(Expr::new(ExprKind::Literal(lt), tk.get_span()));
             }
         }
 
         if self.is_ident() {
-            return Ok(Expr::Var(self.previous().unwrap()))
+            let name = self.previous().unwrap();
+            return Ok(Expr::new(ExprKind::Var(name), self.span_from(start)))
+        }
+
+        if self.curr_match(&vec![TokenType::This]) {
+            let keyword = self.previous().unwrap();
+            return Ok(Expr::new(ExprKind::This(keyword), self.span_from(start)))
+        }
+
+        if self.curr_match(&vec![TokenType::Super]) {
+            let keyword = self.previous().unwrap();
+            self.consume(TokenType::Dot, "Expected '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expected superclass method name.")?;
+            return Ok(Expr::new(ExprKind::Super(keyword, method), self.span_from(start)))
         }
 
         if self.curr_match(&vec![TokenType::LeftParen]) {
             let expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expected ')' after expr")?;
-            return Ok(Expr::Grouping(Box::new(expr)));
+            return Ok(Expr::new(ExprKind::Grouping(Box::new(expr)), self.span_from(start)));
         }
-        let u_tk = &self.peek().unwrap().clone();
-        self.error(u_tk, "Unexpected token");
-        Err(ParserError)
+
+        if self.curr_match(&vec![TokenType::LeftBracket]) {
+            let mut elements = Vec::new();
+
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.ternary()?);
+                    if !self.curr_match(&vec![TokenType::Comma]) || self.check(&TokenType::RightBracket) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume(TokenType::RightBracket, "Expected ']' after array elements")?;
+            return Ok(Expr::new(ExprKind::Array(elements), self.span_from(start)));
+        }
+
+        if self.curr_match(&vec![TokenType::LeftBrace]) {
+            let brace = self.previous().unwrap();
+            let mut entries = Vec::new();
+
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let key = self.ternary()?;
+                    self.consume(TokenType::Colon, "Expected ':' after map key")?;
+                    entries.push((key, self.ternary()?));
+                    if !self.curr_match(&vec![TokenType::Comma]) || self.check(&TokenType::RightBrace) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume(TokenType::RightBrace, "Expected '}' after map entries")?;
+            return Ok(Expr::new(ExprKind::Map(brace, entries), self.span_from(start)));
+        }
+
+        let u_tk = &self.peek_or_end();
+        let msg = if let TokenType::Eof = u_tk.get_type() { "Unexpected end of input" } else { "Unexpected token" };
+        Err(self.error(u_tk, msg))
     }
 
     fn synchronize(&mut self) {
         self.advance();
 
-        while let Some(_) = self.peek() {
-            if let Some(y) = &self.previous {
-                if let TokenType::Semicolon = y.get_type() {
-                    return;
-                }
+        while let Some(next) = self.peek() {
+            if let Some(TokenType::Semicolon) = self.previous().map(|tk| tk.get_type().clone()) {
+                return;
             }
 
-            match self.peek().unwrap().get_type() {
+            match next.get_type() {
                 TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Global
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
+                | TokenType::Do
+                | TokenType::Switch
                 | TokenType::Print
                 | TokenType::Return => return,
                 _ => (),
@@ -390,48 +865,69 @@ impl<'a> Parser<'a> {
 
     fn consume(&mut self, ty: TokenType, msg: &'static str) -> Result<Token> {
         if self.check(&ty) {
-            return Ok(self.advance().unwrap());
+            Ok(self.advance().unwrap())
         } else {
-        	let errored_tok = self.peek().unwrap().clone();
-            self.error(&errored_tok, msg);
-            Err(ParserError)
+        	let errored_tok = self.peek_or_end();
+            Err(self.error(&errored_tok, &expected_found(msg, &errored_tok)))
         }
     }
 
-    fn error(&mut self, token: &Token, msg: &'static str) {
+    fn error(&mut self, token: &Token, msg: &str) -> ParserError {
         if let TokenType::Eof = token.get_type() {
-            self.err_rep.report(token.get_line(), "at end", msg)
+            self.err_rep.error_at_place(token.get_line(), token.get_column(), "at end", msg);
+            ParserError::UnexpectedEof
         } else {
             self.err_rep
-                .report(token.get_line(), token.get_lexeme(), msg)
+                .error_at_place(token.get_line(), token.get_column(), token.get_lexeme(), msg);
+            ParserError::Syntax
         }
     }
 
     fn equality(&mut self) -> Result<Expr> {
         self.match_left_asoc(
             vec![TokenType::BangEqual, TokenType::EqualEqual],
-            |x: &mut Parser| x.comparison(),
+            |x: &mut Parser| x.bit_or(),
         )
     }
 
+    fn bit_or(&mut self) -> Result<Expr> {
+        self.match_left_asoc(vec![TokenType::Pipe], |x: &mut Parser| x.bit_xor())
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr> {
+        self.match_left_asoc(vec![TokenType::Caret], |x: &mut Parser| x.bit_and())
+    }
+
+    fn bit_and(&mut self) -> Result<Expr> {
+        self.match_left_asoc(vec![TokenType::Ampersand], |x: &mut Parser| x.comparison())
+    }
+
     
     fn logic_and(&mut self) -> Result<Expr> {
-        self.match_two_operand(vec![TokenType::And], |x: &mut Parser| x.equality(),|left,op,right| Expr::Logical(left,op,right))
+        self.match_two_operand(vec![TokenType::And], |x: &mut Parser| x.equality(),ExprKind::Logical)
+    }
+
+    fn logic_xor(&mut self) -> Result<Expr> {
+        self.match_two_operand(vec![TokenType::Xor], |x: &mut Parser| x.logic_and(), ExprKind::Logical)
     }
 
     fn logic_or(&mut self) -> Result<Expr> {
-        self.match_two_operand(vec![TokenType::Or], |x: &mut Parser| x.logic_and(), |left,op,right| Expr::Logical(left,op,right))
+        self.match_two_operand(vec![TokenType::Or], |x: &mut Parser| x.logic_xor(), ExprKind::Logical)
     }
 
     fn ternary(&mut self) -> Result<Expr> {
     	let left = self.logic_or()?;
 
     	if self.curr_match(&vec![TokenType::QuestionMark]) {
-    		let tk = self.previous.take().unwrap();
-    		let t_cond = self.logic_or()?;
+    		let tk = self.previous().unwrap();
+    		// The middle is delimited by '?' and ':' so it can be any expression,
+    		// the else branch recurses into assignment so that nested ternaries
+    		// group to the right.
+    		let t_cond = self.expression()?;
     		self.consume(TokenType::Colon, "Expected to find ':' after expr")?;
-    		let f_cond = self.logic_or()?;
-    		Ok(Expr::Ternary(tk,Box::new(left), Box::new(t_cond), Box::new(f_cond)))
+    		let f_cond = self.assignment()?;
+    		let span = join(&left, &f_cond);
+    		Ok(Expr::new(ExprKind::Ternary(tk,Box::new(left), Box::new(t_cond), Box::new(f_cond)), span))
 
     	} else {
     		Ok(left)
@@ -441,7 +937,7 @@ impl<'a> Parser<'a> {
     fn comma(&mut self) -> Result<Expr> {
         self.match_left_asoc(
             vec![TokenType::Comma],
-            |x: &mut Parser| x.ternary(),
+            |x: &mut Parser| x.assignment(),
         )
     }
 
@@ -450,10 +946,10 @@ impl<'a> Parser<'a> {
         matchees: Vec<TokenType>,
         higher_precedence: T,
     ) -> Result<Expr> {
-        self.match_two_operand(matchees, higher_precedence, |left,op,right| Expr::Binary(left,op,right))
+        self.match_two_operand(matchees, higher_precedence, ExprKind::Binary)
     }
 
-    fn match_two_operand<T: Fn(&mut Parser) -> Result<Expr>, V: Fn(Box<Expr>,Token,Box<Expr>) -> Expr>(
+    fn match_two_operand<T: Fn(&mut Parser) -> Result<Expr>, V: Fn(Box<Expr>,Token,Box<Expr>) -> ExprKind>(
         &mut self,
         matchees: Vec<TokenType>,
         higher_precedence: T,
@@ -464,9 +960,338 @@ impl<'a> Parser<'a> {
         while self.curr_match(&matchees) {
             let op = self.previous().unwrap();
             let right = higher_precedence(self)?;
-            expr = combinator(Box::new(expr), op, Box::new(right));
+            let span = join(&expr, &right);
+            expr = Expr::new(combinator(Box::new(expr), op, Box::new(right)), span);
         }
         Ok(expr)
     }
 }
 
+// The span from the start of one node to the end of another.
+fn join(first: &Expr, last: &Expr) -> Range<usize> {
+    first.span.start..last.span.end
+}
+
+// Adds what was found instead to a message saying what was expected. At the
+// end of input the message stays as it is, since the error is placed at end.
+fn expected_found(msg: &str, found: &Token) -> String {
+    let kind = match found.get_type() {
+        TokenType::Eof => return msg.to_owned(),
+        TokenType::Literal(Literal::String(_)) => "String".to_owned(),
+        TokenType::Literal(Literal::Number(_)) => "Number".to_owned(),
+        ty => format!("{:?}", ty),
+    };
+
+    format!("{}, found '{}' ({})", msg.trim_end_matches('.'), found.get_lexeme(), kind)
+}
+
+// Maps a compound assignment token such as `+=`, or an increment, onto the
+// binary operator it desugars to, or None for a plain `=`.
+pub fn compound_operator(tk: &Token) -> Option<Token> {
+    let (ty, lexeme) = match tk.get_type() {
+        TokenType::PlusEqual | TokenType::PlusPlus => (TokenType::Plus, "+"),
+        TokenType::MinusEqual | TokenType::MinusMinus => (TokenType::Minus, "-"),
+        TokenType::StarEqual => (TokenType::Star, "*"),
+        TokenType::SlashEqual => (TokenType::Slash, "/"),
+        _ => return None,
+    };
+
+    Some(Token::new(ty, lexeme.to_owned(), tk.get_line(), tk.get_column()).with_offset(tk.get_span().start))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::PrettyPrint;
+    use crate::tokens::Scanner;
+    use crate::ErrorReporter;
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+        let stmts = Parser::new(tokens, &mut err_rep).parse();
+        assert!(!err_rep.had_error);
+        stmts.ok().unwrap()
+    }
+
+    fn print_exprs(src: &str) -> Vec<String> {
+        let printer = PrettyPrint{};
+        parse(src).iter().map(|stmt| match &stmt.kind {
+            StmtKind::Expr(expr) => expr.accept(&printer),
+            _ => panic!("expected an expression statement"),
+        }).collect()
+    }
+
+    #[test]
+    fn empty_statements() {
+        let stmts = parse(";;");
+        assert_eq!(stmts.len(), 2);
+        assert!(matches!(stmts[0].kind, StmtKind::Empty(1)));
+        assert!(matches!(stmts[1].kind, StmtKind::Empty(1)));
+    }
+
+    #[test]
+    fn for_with_empty_clauses_and_body() {
+        let stmts = parse("for (;;) ;");
+        assert_eq!(stmts.len(), 1);
+        if let StmtKind::While(None, cond, body, None) = &stmts[0].kind {
+            assert!(matches!(cond.kind, ExprKind::Literal(Literal::Boolean(true))));
+            assert!(matches!(body.kind, StmtKind::Empty(1)));
+        } else {
+            panic!("expected a while loop");
+        }
+    }
+
+    #[test]
+    fn compound_assignment_desugars() {
+        assert_eq!(print_exprs("x += 1; y /= 2 * 3;"), vec!["(=x (+ x 1))", "(=y (/ y (* 2 3)))"]);
+    }
+
+    #[test]
+    fn compound_assignment_needs_variable_target() {
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new("1 -= 2;".to_owned(), &mut err_rep).scan_tokens();
+        let _ = Parser::new(tokens, &mut err_rep).parse();
+        assert!(err_rep.had_error);
+    }
+
+    #[test]
+    fn index_and_slice() {
+        assert_eq!(print_exprs("a[0][1]; s[1:3]; s[:];"), vec!["(index (index a 0) 1)", "(slice s 1 3)", "(slice s _ _)"]);
+    }
+
+    #[test]
+    fn ternary_is_right_associative() {
+        assert_eq!(print_exprs("a ? b : c ? d : e;"), vec!["(? a b (? c d e))"]);
+        assert_eq!(print_exprs("a ? b ? c : d : e;"), vec!["(? a (? b c d) e)"]);
+    }
+
+    #[test]
+    fn assignment_comma_and_ternary_precedence() {
+        assert_eq!(print_exprs("a = b ? c : d;"), vec!["(=a (? b c d))"]);
+        assert_eq!(print_exprs("a = 1, 2;"), vec!["(, (=a 1) 2)"]);
+        assert_eq!(print_exprs("a, b = 1;"), vec!["(, a (=b 1))"]);
+        assert_eq!(print_exprs("a = b = c, d;"), vec!["(, (=a (=b c)) d)"]);
+        assert_eq!(print_exprs("a ? b, c : d;"), vec!["(? a (, b c) d)"]);
+        assert_eq!(print_exprs("a ? b : c, d;"), vec!["(, (? a b c) d)"]);
+        assert_eq!(print_exprs("x = a ? b : c = d;"), vec!["(=x (? a b (=c d)))"]);
+        assert_eq!(print_exprs("a or b ? c : d;"), vec!["(? (or a b) c d)"]);
+        assert_eq!(print_exprs("a ? b and c : d or e;"), vec!["(? a (and b c) (or d e))"]);
+
+        for src in ["(a, b) = 1;", "(a = b) = c;", "a + b = c;"].iter() {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_string(), &mut err_rep).scan_tokens();
+            let _ = Parser::new(tokens, &mut err_rep).parse();
+            assert!(err_rep.diagnostics().iter().any(|d| d.message == "Invalid assignment target."), "{} should not be assignable", src);
+        }
+    }
+
+    #[test]
+    fn ternary_branches_allow_assignment() {
+        assert_eq!(print_exprs("c ? x = 1 : y = 2;"), vec!["(? c (=x 1) (=y 2))"]);
+        assert_eq!(print_exprs("z = c ? x : y;"), vec!["(=z (? c x y))"]);
+    }
+
+    #[test]
+    fn call_arguments_are_not_comma_expressions() {
+        assert_eq!(print_exprs("f(1, 2, 3); f((1, 2));"), vec!["(call f 1 2 3)", "(call f (group (, 1 2)))"]);
+    }
+
+    #[test]
+    fn call_argument_limit() {
+        let call = |n: usize, max_args: Option<usize>| {
+            let src = format!("f({});", vec!["1"; n].join(", "));
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src, &mut err_rep).scan_tokens();
+            let parsed = Parser::new(tokens, &mut err_rep).with_max_args(max_args).parse().is_ok();
+            (parsed, err_rep.diagnostics().iter().map(|d| d.message.clone()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(call(MAX_ARGS, Some(MAX_ARGS)), (true, vec![]));
+        assert_eq!(call(MAX_ARGS + 3, Some(MAX_ARGS)), (true, vec!["Cannot have more than 255 arguments.".to_owned()]));
+        assert_eq!(call(3, Some(2)).1, vec!["Cannot have more than 2 arguments."]);
+        assert_eq!(call(1000, None), (true, vec![]));
+    }
+
+    #[test]
+    fn increments_need_a_variable() {
+        assert_eq!(print_exprs("++x; x--; -x++; a[i++];"),
+            vec!["(=x (+ x 1))", "(x--)", "(- (x++))", "(index a (i++))"]);
+
+        for src in ["1++;", "++a.b;", "(x)--;"].iter() {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_string(), &mut err_rep).scan_tokens();
+            assert!(Parser::new(tokens, &mut err_rep).parse().is_ok());
+            assert_eq!(err_rep.diagnostics()[0].message, "Invalid increment target.", "{}", src);
+        }
+    }
+
+    #[test]
+    fn chained_comparisons_are_rejected() {
+        assert_eq!(print_exprs("(1 < 2) < 3; 1 < 2 == 3 > 4; 1 < 2 and 2 < 3;"),
+            vec!["(< (group (< 1 2)) 3)", "(== (< 1 2) (> 3 4))", "(and (< 1 2) (< 2 3))"]);
+
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new("print 1 < 2 < 3 >= 4;".to_owned(), &mut err_rep).scan_tokens();
+        assert!(Parser::new(tokens, &mut err_rep).parse().is_ok());
+        let messages: Vec<&str> = err_rep.diagnostics().iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["Chained comparison is not allowed; use explicit parentheses or 'and'."; 2]);
+    }
+
+    // Each node spans the source text it was parsed from, including nodes
+    // made up for desugared syntax.
+    #[test]
+    fn nodes_span_their_source() {
+        let src = "var a = (1 + b) * c;\nfor (var i = 0; i < 3; i++) { print f(i)[0]; }\nx += 2;";
+        let text = |span: &Range<usize>| &src[span.clone()];
+        let stmts = parse(src);
+        assert_eq!(stmts.iter().map(|stmt| text(&stmt.span)).collect::<Vec<_>>(),
+            vec!["var a = (1 + b) * c;", "for (var i = 0; i < 3; i++) { print f(i)[0]; }", "x += 2;"]);
+
+        match &stmts[0].kind {
+            StmtKind::Var(_, Some(init)) => match &init.kind {
+                ExprKind::Binary(left, _, right) => {
+                    assert_eq!(text(&init.span), "(1 + b) * c");
+                    assert_eq!(text(&left.span), "(1 + b)");
+                    assert_eq!(text(&right.span), "c");
+                },
+                _ => panic!("expected a binary expression")
+            },
+            _ => panic!("expected a variable declaration")
+        }
+
+        match &stmts[1].kind {
+            StmtKind::Block(stmts) => {
+                assert_eq!(text(&stmts[0].span), "var i = 0;");
+                assert_eq!(text(&stmts[1].span), "for (var i = 0; i < 3; i++) { print f(i)[0]; }");
+                match &stmts[1].kind {
+                    StmtKind::While(_, cond, body, Some(increment)) => {
+                        assert_eq!(text(&cond.span), "i < 3");
+                        assert_eq!(text(&increment.span), "i++");
+                        assert_eq!(text(&body.span), "{ print f(i)[0]; }");
+                    },
+                    _ => panic!("expected a while loop")
+                }
+            },
+            _ => panic!("expected a block")
+        }
+
+        match &stmts[2].kind {
+            StmtKind::Expr(Expr { kind: ExprKind::Assignment(_, value), span }) => {
+                assert_eq!(text(span), "x += 2");
+                assert_eq!(text(&value.span), "x += 2");
+            },
+            _ => panic!("expected an assignment")
+        }
+
+        match &parse("for (;;) { }")[0].kind {
+            StmtKind::While(_, cond, _, _) => assert_eq!(cond.span, 6..6),
+            _ => panic!("expected a while loop")
+        }
+    }
+
+    #[test]
+    fn property_access_chains_to_the_left() {
+        assert_eq!(print_exprs("a.b.c; a.b(1).c;"), vec!["(. (. a b) c)", "(. (call (. a b) 1) c)"]);
+        assert_eq!(print_exprs("a.b = c; a.b.c = d = e;"), vec!["(.= a b c)", "(.= (. a b) c (=d e))"]);
+        let stmts = parse("a.b.c = 1;");
+        match &stmts[..] {
+            [Stmt { kind: StmtKind::Expr(Expr { kind: ExprKind::Set(object, name, _), .. }), .. }] => {
+                assert!(matches!(object.kind, ExprKind::Get(..)) && name.get_lexeme() == "c");
+            },
+            _ => panic!("expected a property assignment")
+        }
+    }
+
+    #[test]
+    fn unclosed_block_is_incomplete() {
+        let parse_err = |src: &str| {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+            Parser::new(tokens, &mut err_rep).parse().err()
+        };
+
+        assert_eq!(parse_err("if (x) {"), Some(ParserError::UnexpectedEof));
+        assert_eq!(parse_err("print (1 +"), Some(ParserError::UnexpectedEof));
+        assert_eq!(parse_err("print 1 +;"), Some(ParserError::Syntax));
+    }
+
+    #[test]
+    fn trailing_commas() {
+        assert_eq!(print_exprs("f(1, 2,); f(); [1, 2,]; {\"a\": 1,};"),
+            vec!["(call f 1 2)", "(call f)", "(array 1 2)", "(map \"a\" 1)"]);
+
+        for src in ["f(,);", "[,];", "f(1,,);"].iter() {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_string(), &mut err_rep).scan_tokens();
+            assert!(Parser::new(tokens, &mut err_rep).parse().is_err(), "{} should not parse", src);
+        }
+    }
+    #[test]
+    fn consume_errors_name_the_found_token() {
+        let messages = |src: &str| {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+            let _ = Parser::new(tokens, &mut err_rep).parse();
+            err_rep.into_diagnostics().into_iter().map(|d| d.message).collect::<Vec<_>>()
+        };
+
+        assert_eq!(messages("{ print 1 }"), vec!["Expected ';' after value, found '}' (RightBrace)"]);
+        assert_eq!(messages("var 1 = 2;"), vec!["Expected variable name, found '1' (Number)"]);
+        assert_eq!(messages("print (1"), vec!["Expected ')' after expr"]);
+    }
+
+    #[test]
+    fn tokens_without_eof_end_gracefully() {
+        let mut err_rep = ErrorReporter::new();
+        assert_eq!(Parser::new(Vec::new(), &mut err_rep).parse_expression().err(), Some(ParserError::UnexpectedEof));
+        assert_eq!(err_rep.diagnostics()[0].message, "Unexpected end of input");
+        assert!(Parser::new(Vec::new(), &mut ErrorReporter::new()).parse().ok().unwrap().is_empty());
+
+        let mut err_rep = ErrorReporter::new();
+        let mut tokens = Scanner::new("print 1".to_owned(), &mut err_rep).scan_tokens();
+        tokens.pop();
+        assert_eq!(Parser::new(tokens, &mut err_rep).parse().err(), Some(ParserError::UnexpectedEof));
+        assert_eq!(err_rep.diagnostics()[0].message, "Expected ';' after value");
+        assert_eq!(err_rep.diagnostics()[0].column, Some(8));
+    }
+
+    #[test]
+    fn missing_semicolon_at_line_end_is_forgiven() {
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new("print 1\nprint 2;".to_owned(), &mut err_rep).scan_tokens();
+        let stmts = Parser::new(tokens, &mut err_rep).parse().ok().unwrap();
+        let printed: Vec<String> = stmts.iter().map(|stmt| stmt.accept(&PrettyPrint)).collect();
+        assert_eq!(printed, vec!["(print 1)", "(print 2)"]);
+        assert_eq!(err_rep.diagnostics().len(), 1);
+        assert_eq!(err_rep.diagnostics()[0].line, 2);
+
+        for src in ["print 1 print 2;", "print 1"].iter() {
+            let mut err_rep = ErrorReporter::new();
+            let tokens = Scanner::new(src.to_string(), &mut err_rep).scan_tokens();
+            assert!(Parser::new(tokens, &mut err_rep).parse().is_err(), "{} should not parse", src);
+        }
+    }
+
+    #[test]
+    fn import_takes_a_string_path() {
+        let printed: Vec<String> = parse("import \"utils.lox\";").iter().map(|stmt| stmt.accept(&PrettyPrint)).collect();
+        assert_eq!(printed, vec!["(import \"utils.lox\")"]);
+
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new("import utils;".to_owned(), &mut err_rep).scan_tokens();
+        assert!(Parser::new(tokens, &mut err_rep).parse().is_err());
+        assert_eq!(err_rep.diagnostics()[0].message, "Expected a file path after 'import', found 'utils' (Identifier)");
+    }
+
+    #[test]
+    fn labels_go_on_loops() {
+        let printed: Vec<String> = parse("a: while (x) break a; b: for (;;) continue; c: do continue c; while (y);").iter().map(|stmt| stmt.to_string()).collect();
+        assert_eq!(printed, vec!["(while a: x (break a:))", "(while b: true (continue))", "(do c: (continue c:) y)"]);
+
+        let mut err_rep = ErrorReporter::new();
+        let tokens = Scanner::new("a: print 1;".to_owned(), &mut err_rep).scan_tokens();
+        assert!(Parser::new(tokens, &mut err_rep).parse().is_err());
+        assert_eq!(err_rep.diagnostics()[0].message, "Expected a loop after label, found 'print' (Print)");
+    }
+}
@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// An interned name. Environments key their bindings by symbol so that a
+// lookup hashes a u32 instead of the name's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// Names are never released, so a symbol stays valid for the life of the
+// thread that interned it.
+#[derive(Default)]
+struct SymbolTable {
+	ids: HashMap<Rc<str>, Symbol>,
+	names: Vec<Rc<str>>
+}
+
+thread_local! {
+	static SYMBOLS: RefCell<SymbolTable> = RefCell::new(SymbolTable::default());
+}
+
+impl Symbol {
+	pub fn intern(name: &str) -> Symbol {
+		SYMBOLS.with(|table| {
+			let mut table = table.borrow_mut();
+			if let Some(&sym) = table.ids.get(name) {
+				return sym;
+			}
+
+			let sym = Symbol(table.names.len() as u32);
+			let name: Rc<str> = name.into();
+			table.names.push(name.clone());
+			table.ids.insert(name, sym);
+			sym
+		})
+	}
+
+	pub fn name(self) -> Rc<str> {
+		SYMBOLS.with(|table| table.borrow().names[self.0 as usize].clone())
+	}
+}
+
+// Where the checker found the declaration a name refers to: a local scope
+// some number of scopes out from the one using it, at the position the
+// name was declared in, or else the globals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+	Local { depth: usize, index: usize },
+	Global
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn interning_is_stable() {
+		let a = Symbol::intern("a");
+		assert_eq!(Symbol::intern("a"), a);
+		assert_ne!(Symbol::intern("b"), a);
+		assert_eq!(&*a.name(), "a");
+	}
+}
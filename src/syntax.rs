@@ -1,8 +1,22 @@
 use crate::tokens::Token;
 use crate::tokens::Literal;
 
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+// A node and the bytes of the source it was parsed from. Nodes the parser
+// makes up, such as the step `x++` takes, span the text they stand for.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Expr {
+	pub kind: ExprKind,
+	pub span: Range<usize>
+}
+
 #[derive(Debug)]
-pub enum Expr {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ExprKind {
 	Binary(Box<Expr>, Token, Box<Expr>),
 	Ternary(Token, Box<Expr>,Box<Expr>,Box<Expr>),
 	Grouping(Box<Expr>),
@@ -10,32 +24,85 @@ pub enum Expr {
 	Var(Token),
 	Unary(Token, Box<Expr>),
 	Assignment(Token, Box<Expr>),
+	// `x++` or `x--`, which yield the value from before the step.
+	Postfix(Token, Token),
 	Logical(Box<Expr>, Token, Box<Expr>),
 	Call(Box<Expr>, Token, Vec<Expr>),
+	Array(Vec<Expr>),
+	Index(Box<Expr>, Token, Box<Expr>),
+	Slice(Box<Expr>, Token, Option<Box<Expr>>, Option<Box<Expr>>),
+	Map(Token, Vec<(Expr, Expr)>),
+	SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+	Get(Box<Expr>, Token),
+	Set(Box<Expr>, Token, Box<Expr>),
+	This(Token),
+	Super(Token, Token),
 }
 
+// Shared so that closures can keep their declaration alive without copying
+// the body.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FunctionDecl {
+	pub name: Token,
+	pub params: Vec<Token>,
+	pub body: Vec<Stmt>,
+	// From the name to the closing brace.
+	pub span: Range<usize>
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stmt {
+	pub kind: StmtKind,
+	pub span: Range<usize>
+}
 
 #[derive(Debug)]
-pub enum Stmt {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StmtKind {
 	Print(Expr),
 	Expr(Expr),
 	Var(Token, Option<Expr>),
+	Global(Token, Option<Expr>),
 	Block(Vec<Stmt>),
 	If(Box<Expr>, Box<Stmt>, Box<Option<Stmt>>),
-	While(Box<Expr>, Box<Stmt>),
-	Break(usize)
+	// A label, the condition, the body and, for a for loop, the increment.
+	While(Option<Token>, Box<Expr>, Box<Stmt>, Option<Box<Expr>>),
+	DoWhile(Option<Token>, Box<Stmt>, Box<Expr>),
+	Switch(Expr, Vec<(Expr, Vec<Stmt>)>, Option<Vec<Stmt>>),
+	Break(usize, Option<Token>),
+	Continue(usize, Option<Token>),
+	Empty(usize),
+	Function(Rc<FunctionDecl>),
+	Return(Token, Option<Expr>),
+	Class(Token, Option<Expr>, Vec<Rc<FunctionDecl>>),
+	Import(Token, String)
 }
 
 impl Stmt {
+	pub fn new(kind: StmtKind, span: Range<usize>) -> Stmt {
+		Stmt { kind, span }
+	}
+
 	pub fn accept<R>(&self, visitor: impl StmtVisitor<R>) -> R {
-		match self {
-			Stmt::Print(exp) => visitor.visit_print(exp),
-			Stmt::Expr(exp) => visitor.visit_expr_statement(exp),
-			Stmt::Var(name,expr) => visitor.visit_variable(name, expr),
-			Stmt::Block(stmts) => visitor.visit_block_stmt(stmts),
-			Stmt::If(cond, then, otherwise) => visitor.visit_if(cond,then,otherwise),
-			Stmt::While(cond, then) => visitor.visit_while(cond,then),
-			Stmt::Break(line) => visitor.visit_break(*line)
+		match &self.kind {
+			StmtKind::Print(exp) => visitor.visit_print(exp),
+			StmtKind::Expr(exp) => visitor.visit_expr_statement(exp),
+			StmtKind::Var(name,expr) => visitor.visit_variable(name, expr),
+			StmtKind::Global(name,expr) => visitor.visit_global(name, expr),
+			StmtKind::Block(stmts) => visitor.visit_block_stmt(stmts),
+			StmtKind::If(cond, then, otherwise) => visitor.visit_if(cond,then,otherwise),
+			StmtKind::While(label, cond, then, increment) => visitor.visit_while(label,cond,then,increment),
+			StmtKind::DoWhile(label, body, cond) => visitor.visit_do_while(label,body,cond),
+			StmtKind::Switch(subject, cases, default) => visitor.visit_switch(subject,cases,default),
+			StmtKind::Break(line, label) => visitor.visit_break(*line, label),
+			StmtKind::Continue(line, label) => visitor.visit_continue(*line, label),
+			StmtKind::Empty(line) => visitor.visit_empty(*line),
+			StmtKind::Function(decl) => visitor.visit_function(decl),
+			StmtKind::Return(keyword, value) => visitor.visit_return(keyword, value),
+			StmtKind::Class(name, superclass, methods) => visitor.visit_class(name, superclass, methods),
+			StmtKind::Import(keyword, path) => visitor.visit_import(keyword, path)
 		}
 	}
 }
@@ -44,10 +111,19 @@ pub trait StmtVisitor <R> {
 	fn visit_print(self, expr: &Expr) -> R;
 	fn visit_expr_statement(self, expr: &Expr) -> R;
 	fn visit_variable(self, name: &Token, expr: &Option<Expr>) -> R;
-	fn visit_block_stmt(self,stmts: &Vec<Stmt>) -> R; 
+	fn visit_global(self, name: &Token, expr: &Option<Expr>) -> R;
+	fn visit_block_stmt(self,stmts: &[Stmt]) -> R; 
 	fn visit_if(self, cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) -> R;
-	fn visit_while(self, cond: &Expr, then: &Stmt) -> R;
-	fn visit_break(self, line: usize) -> R;
+	fn visit_while(self, label: &Option<Token>, cond: &Expr, then: &Stmt, increment: &Option<Box<Expr>>) -> R;
+	fn visit_do_while(self, label: &Option<Token>, body: &Stmt, cond: &Expr) -> R;
+	fn visit_switch(self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) -> R;
+	fn visit_break(self, line: usize, label: &Option<Token>) -> R;
+	fn visit_continue(self, line: usize, label: &Option<Token>) -> R;
+	fn visit_empty(self, line: usize) -> R;
+	fn visit_function(self, decl: &Rc<FunctionDecl>) -> R;
+	fn visit_return(self, keyword: &Token, value: &Option<Expr>) -> R;
+	fn visit_class(self, name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) -> R;
+	fn visit_import(self, keyword: &Token, path: &str) -> R;
 }
 
 pub trait ExprVisitor <R> {
@@ -57,29 +133,76 @@ pub trait ExprVisitor <R> {
 	fn visit_unary(self,op: &Token, exp: &Expr) -> R;
 	fn visit_ternary(self, op: &Token, left: &Expr, middle: &Expr, right: &Expr) -> R;
 	fn visit_assignment(self, name: &Token, value: &Expr) -> R;
+	fn visit_postfix(self, name: &Token, op: &Token) -> R;
 	fn visit_variable_expr(self, name: &Token) -> R;
 	fn visit_logical(self, left: &Expr, op: &Token, right: &Expr) -> R;
-	fn visit_call(self, callee: &Expr, paren: &Token, args: &Vec<Expr>) -> R;
+	fn visit_call(self, callee: &Expr, paren: &Token, args: &[Expr]) -> R;
+	fn visit_array(self, elements: &[Expr]) -> R;
+	fn visit_index(self, object: &Expr, bracket: &Token, index: &Expr) -> R;
+	fn visit_slice(self, object: &Expr, bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) -> R;
+	fn visit_map(self, brace: &Token, entries: &[(Expr, Expr)]) -> R;
+	fn visit_set_index(self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> R;
+	fn visit_get(self, object: &Expr, name: &Token) -> R;
+	fn visit_set(self, object: &Expr, name: &Token, value: &Expr) -> R;
+	fn visit_this(self, keyword: &Token) -> R;
+	fn visit_super(self, keyword: &Token, method: &Token) -> R;
 }
 
 impl Expr {
+	pub fn new(kind: ExprKind, span: Range<usize>) -> Expr {
+		Expr { kind, span }
+	}
+
 	pub fn accept<R,T: ExprVisitor<R>>(&self,visitor: T) -> R {
-		match self {
-			Expr::Binary(exp,op,exp2) => visitor.visit_binary(exp, op, exp2),
-			Expr::Grouping(exp) => visitor.visit_grouping(exp),
-			Expr::Literal(lt) => visitor.visit_literal(lt),
-			Expr::Unary(op, exp) =>  visitor.visit_unary(op, exp),
-			Expr::Ternary(op, left, middle, right) => visitor.visit_ternary(op, left, middle, right),
-			Expr::Var(nm) => visitor.visit_variable_expr(nm),
-			Expr::Assignment(nm, val) => visitor.visit_assignment(nm, val),
-			Expr::Logical(left,op,right) => visitor.visit_logical(left, op, right),
-			Expr::Call(callee, paren, args) => visitor.visit_call(callee,paren,args)
+		match &self.kind {
+			ExprKind::Binary(exp,op,exp2) => visitor.visit_binary(exp, op, exp2),
+			ExprKind::Grouping(exp) => visitor.visit_grouping(exp),
+			ExprKind::Literal(lt) => visitor.visit_literal(lt),
+			ExprKind::Unary(op, exp) =>  visitor.visit_unary(op, exp),
+			ExprKind::Ternary(op, left, middle, right) => visitor.visit_ternary(op, left, middle, right),
+			ExprKind::Var(nm) => visitor.visit_variable_expr(nm),
+			ExprKind::Assignment(nm, val) => visitor.visit_assignment(nm, val),
+			ExprKind::Postfix(nm, op) => visitor.visit_postfix(nm, op),
+			ExprKind::Logical(left,op,right) => visitor.visit_logical(left, op, right),
+			ExprKind::Call(callee, paren, args) => visitor.visit_call(callee,paren,args),
+			ExprKind::Array(elements) => visitor.visit_array(elements),
+			ExprKind::Index(object, bracket, index) => visitor.visit_index(object, bracket, index),
+			ExprKind::Slice(object, bracket, start, end) => visitor.visit_slice(object, bracket, start, end),
+			ExprKind::Map(brace, entries) => visitor.visit_map(brace, entries),
+			ExprKind::SetIndex(object, bracket, index, value) => visitor.visit_set_index(object, bracket, index, value),
+			ExprKind::Get(object, name) => visitor.visit_get(object, name),
+			ExprKind::Set(object, name, value) => visitor.visit_set(object, name, value),
+			ExprKind::This(keyword) => visitor.visit_this(keyword),
+			ExprKind::Super(keyword, method) => visitor.visit_super(keyword, method)
 		}
 	} 
 }
 
+// Renders syntax trees as Lisp-style S-expressions, and the output is kept
+// stable for tools to rely on. Every node but a literal or a name prints as
+// `(head child...)` with children separated by single spaces:
+// - operators use their lexeme as the head, so `1 + 2` is `(+ 1 2)` and
+//   `-x` is `(- x)`; assignment joins the name on, as in `(=x 1)`
+// - other nodes use a keyword head such as `group`, `call`, `array`,
+//   `index`, `var`, `block`, `if` or `while`
+// - numbers print without a trailing `.0`, strings in double quotes, and
+//   `nil`, `true` and `false` as written
+// - a slice bound that was left out prints as `_`
 pub struct PrettyPrint;
 
+// Both print as the S-expressions PrettyPrint produces.
+impl fmt::Display for Expr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.accept(&PrettyPrint))
+	}
+}
+
+impl fmt::Display for Stmt {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.accept(&PrettyPrint))
+	}
+}
+
 
 impl ExprVisitor<String> for &PrettyPrint {
 	fn visit_binary(self,left: &Expr, op: &Token, right: &Expr) -> String {
@@ -94,20 +217,85 @@ impl ExprVisitor<String> for &PrettyPrint {
 		total
 	}
 
-	fn visit_call(self,left: &Expr, paren: &Token, args: &Vec<Expr>) -> String {
+	fn visit_call(self,left: &Expr, _paren: &Token, args: &[Expr]) -> String {
 		let mut total = String::new();
-		total.push('(');
+		total.push_str("(call ");
 		total.push_str(&left.accept(self));
-		total.push(' ');
 
 		for xp in args.iter() {
+			total.push(' ');
 			total.push_str(&xp.accept(self));
+		}
+
+		total.push(')');
+		total
+	}
+
+	fn visit_array(self, elements: &[Expr]) -> String {
+		let mut total = String::new();
+		total.push_str("(array");
+
+		for xp in elements.iter() {
 			total.push(' ');
+			total.push_str(&xp.accept(self));
 		}
 
+		total.push(')');
+		total
+	}
+
+	fn visit_index(self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+		let mut total = String::new();
+		total.push_str("(index ");
+		total.push_str(&object.accept(self));
+		total.push(' ');
+		total.push_str(&index.accept(self));
+		total.push(')');
+		total
+	}
+
+	fn visit_slice(self, object: &Expr, _bracket: &Token, start: &Option<Box<Expr>>, end: &Option<Box<Expr>>) -> String {
+		let mut total = String::new();
+		total.push_str("(slice ");
+		total.push_str(&object.accept(self));
+
+		for bound in [start, end].iter() {
+			total.push(' ');
+			match bound {
+				Some(xp) => total.push_str(&xp.accept(self)),
+				None => total.push('_'),
+			}
+		}
+
+		total.push(')');
 		total
 	}
 
+	fn visit_map(self, _brace: &Token, entries: &[(Expr, Expr)]) -> String {
+		let parts: Vec<String> = entries.iter().flat_map(|(k, v)| vec![k.accept(self), v.accept(self)]).collect();
+		parenthesize("map", &parts)
+	}
+
+	fn visit_set_index(self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> String {
+		parenthesize("set", &[object.accept(self), index.accept(self), value.accept(self)])
+	}
+
+	fn visit_get(self, object: &Expr, name: &Token) -> String {
+		parenthesize(".", &[object.accept(self), name.get_lexeme().to_owned()])
+	}
+
+	fn visit_set(self, object: &Expr, name: &Token, value: &Expr) -> String {
+		parenthesize(".=", &[object.accept(self), name.get_lexeme().to_owned(), value.accept(self)])
+	}
+
+	fn visit_this(self, _keyword: &Token) -> String {
+		"this".to_owned()
+	}
+
+	fn visit_super(self, _keyword: &Token, method: &Token) -> String {
+		parenthesize("super", &[method.get_lexeme().to_owned()])
+	}
+
 	fn visit_variable_expr(self, name: &Token) -> String {
 		name.get_lexeme().to_owned()
 	}
@@ -115,17 +303,21 @@ impl ExprVisitor<String> for &PrettyPrint {
 	fn visit_assignment(self, name: &Token, value: &Expr) -> String {
 		let mut total = String::new();
 		total.push_str("(=");
-		total.push_str(&name.get_lexeme());
+		total.push_str(name.get_lexeme());
 		total.push(' ');
 		total.push_str(&value.accept(self));
 		total.push(')');
 		total
 	}
 
+	fn visit_postfix(self, name: &Token, op: &Token) -> String {
+		format!("({}{})", name.get_lexeme(), op.get_lexeme())
+	}
+
 	fn visit_logical(self, left: &Expr, op: &Token, right: &Expr) -> String {
 		let mut total = String::new();
 		total.push('(');
-		total.push_str(&op.get_lexeme());
+		total.push_str(op.get_lexeme());
 		total.push(' ');
 		total.push_str(&left.accept(self));
 		total.push(' ');
@@ -136,7 +328,7 @@ impl ExprVisitor<String> for &PrettyPrint {
 
 	fn visit_grouping(self,exp: &Expr) ->String {
 		let mut total = String::new();
-		total.push_str("(group");
+		total.push_str("(group ");
 		total.push_str(&exp.accept(self));
 		total.push(')');
 		total
@@ -157,7 +349,11 @@ impl ExprVisitor<String> for &PrettyPrint {
 	}
 	
 	fn visit_literal(self,lit: &Literal) -> String {
-		lit.to_string()
+		if let Literal::String(s) = lit {
+			format!("{:?}", s)
+		} else {
+			lit.to_string()
+		}
 	}
 
 	fn visit_unary(self,op: &Token, exp: &Expr) -> String {
@@ -171,17 +367,170 @@ impl ExprVisitor<String> for &PrettyPrint {
 	}
 }
 
+fn parenthesize(name: &str, parts: &[String]) -> String {
+	let mut total = String::new();
+	total.push('(');
+	total.push_str(name);
+
+	for part in parts.iter() {
+		total.push(' ');
+		total.push_str(part);
+	}
+
+	total.push(')');
+	total
+}
+
+fn labelled(label: &Option<Token>) -> Vec<String> {
+	label.iter().map(|l| format!("{}:", l.get_lexeme())).collect()
+}
+
+impl StmtVisitor<String> for &PrettyPrint {
+	fn visit_print(self, expr: &Expr) -> String {
+		parenthesize("print", &[expr.accept(self)])
+	}
+
+	fn visit_expr_statement(self, expr: &Expr) -> String {
+		parenthesize("expr", &[expr.accept(self)])
+	}
+
+	fn visit_variable(self, name: &Token, expr: &Option<Expr>) -> String {
+		let mut parts = vec![name.get_lexeme().to_owned()];
+		parts.extend(expr.iter().map(|xp| xp.accept(self)));
+		parenthesize("var", &parts)
+	}
+
+	fn visit_global(self, name: &Token, expr: &Option<Expr>) -> String {
+		let mut parts = vec![name.get_lexeme().to_owned()];
+		parts.extend(expr.iter().map(|xp| xp.accept(self)));
+		parenthesize("global", &parts)
+	}
+
+	fn visit_block_stmt(self, stmts: &[Stmt]) -> String {
+		let parts: Vec<String> = stmts.iter().map(|st| st.accept(self)).collect();
+		parenthesize("block", &parts)
+	}
+
+	fn visit_if(self, cond: &Expr, then: &Stmt, otherwise: &Option<Stmt>) -> String {
+		let mut parts = vec![cond.accept(self), then.accept(self)];
+		parts.extend(otherwise.iter().map(|st| st.accept(self)));
+		parenthesize("if", &parts)
+	}
+
+	fn visit_while(self, label: &Option<Token>, cond: &Expr, then: &Stmt, increment: &Option<Box<Expr>>) -> String {
+		let mut parts = labelled(label);
+		parts.extend(vec![cond.accept(self), then.accept(self)]);
+		parts.extend(increment.iter().map(|xp| xp.accept(self)));
+		parenthesize("while", &parts)
+	}
+
+	fn visit_do_while(self, label: &Option<Token>, body: &Stmt, cond: &Expr) -> String {
+		let mut parts = labelled(label);
+		parts.extend(vec![body.accept(self), cond.accept(self)]);
+		parenthesize("do", &parts)
+	}
+
+	fn visit_switch(self, subject: &Expr, cases: &[(Expr, Vec<Stmt>)], default: &Option<Vec<Stmt>>) -> String {
+		let mut parts = vec![subject.accept(self)];
+
+		for (value, stmts) in cases.iter() {
+			let mut case = vec![value.accept(self)];
+			case.extend(stmts.iter().map(|st| st.accept(self)));
+			parts.push(parenthesize("case", &case));
+		}
+
+		if let Some(stmts) = default {
+			let case: Vec<String> = stmts.iter().map(|st| st.accept(self)).collect();
+			parts.push(parenthesize("default", &case));
+		}
+
+		parenthesize("switch", &parts)
+	}
+
+	fn visit_break(self, _line: usize, label: &Option<Token>) -> String {
+		parenthesize("break", &labelled(label))
+	}
+
+	fn visit_continue(self, _line: usize, label: &Option<Token>) -> String {
+		parenthesize("continue", &labelled(label))
+	}
+
+	fn visit_empty(self, _line: usize) -> String {
+		parenthesize("empty", &[])
+	}
+
+	fn visit_function(self, decl: &Rc<FunctionDecl>) -> String {
+		let params: Vec<String> = decl.params.iter().map(|p| p.get_lexeme().to_owned()).collect();
+		let mut parts = vec![decl.name.get_lexeme().to_owned(), format!("({})", params.join(" "))];
+		parts.extend(decl.body.iter().map(|st| st.accept(self)));
+		parenthesize("fun", &parts)
+	}
+
+	fn visit_return(self, _keyword: &Token, value: &Option<Expr>) -> String {
+		let parts: Vec<String> = value.iter().map(|xp| xp.accept(self)).collect();
+		parenthesize("return", &parts)
+	}
+
+	fn visit_class(self, name: &Token, superclass: &Option<Expr>, methods: &[Rc<FunctionDecl>]) -> String {
+		let mut parts = vec![name.get_lexeme().to_owned()];
+		parts.extend(superclass.iter().map(|xp| parenthesize("<", &[xp.accept(self)])));
+		parts.extend(methods.iter().map(|m| self.visit_function(m)));
+		parenthesize("class", &parts)
+	}
+
+	fn visit_import(self, _keyword: &Token, path: &str) -> String {
+		parenthesize("import", &[format!("{:?}", path)])
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
 	use crate::tokens::TokenType;
 	use crate::tokens::Token;
+
+	fn expr(kind: ExprKind) -> Expr {
+		Expr::new(kind, 0..0)
+	}
+
+	fn stmt(kind: StmtKind) -> Stmt {
+		Stmt::new(kind, 0..0)
+	}
 	
 	#[test]
 	fn simple_pretty_print() {
-		let e = Expr::Binary(Box::new(Expr::Literal(Literal::Number(2.0))), Token::new(TokenType::Plus,"+".to_owned(),1),Box::new(Expr::Literal(Literal::Number(2.0))) );
+		let e = expr(ExprKind::Binary(Box::new(expr(ExprKind::Literal(Literal::Number(2.0)))), Token::new(TokenType::Plus,"+".to_owned(),1,3),Box::new(expr(ExprKind::Literal(Literal::Number(2.0))))));
 		let visitor = PrettyPrint{};
 		let b = e.accept(&visitor);
 		assert_eq!(b,"(+ 2 2)");
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn statement_pretty_print() {
+		let visitor = PrettyPrint{};
+		let name = Token::new(TokenType::Identifier,"a".to_owned(),1,1);
+		let block = stmt(StmtKind::Block(vec![
+			stmt(StmtKind::Var(name.clone(), Some(expr(ExprKind::Literal(Literal::String("x y".to_owned())))))),
+			stmt(StmtKind::If(Box::new(expr(ExprKind::Var(name.clone()))), Box::new(stmt(StmtKind::Print(expr(ExprKind::Var(name))))), Box::new(Some(stmt(StmtKind::Break(1, None)))))),
+		]));
+		assert_eq!(block.accept(&visitor), "(block (var a \"x y\") (if a (print a) (break)))");
+	}
+
+	#[test]
+	fn displays_as_s_expressions() {
+		let num = |x: f64| Box::new(expr(ExprKind::Literal(Literal::Number(x))));
+		let name = Token::new(TokenType::Identifier,"f".to_owned(),1,1);
+		let paren = Token::new(TokenType::RightParen,")".to_owned(),1,4);
+		let minus = Token::new(TokenType::Minus,"-".to_owned(),1,1);
+
+		assert_eq!(format!("{}", expr(ExprKind::Unary(minus, num(1.0)))), "(- 1)");
+		assert_eq!(format!("{}", expr(ExprKind::Grouping(num(2.5)))), "(group 2.5)");
+		assert_eq!(format!("{}", expr(ExprKind::Call(Box::new(expr(ExprKind::Var(name.clone()))), paren, vec![*num(1.0), expr(ExprKind::Literal(Literal::Nil))]))), "(call f 1 nil)");
+		assert_eq!(format!("{}", expr(ExprKind::Array(vec![*num(1.0), expr(ExprKind::Literal(Literal::String("a".to_owned())))]))), "(array 1 \"a\")");
+		assert_eq!(format!("{}", expr(ExprKind::Assignment(name.clone(), num(3.0)))), "(=f 3)");
+
+		let body = stmt(StmtKind::Block(vec![stmt(StmtKind::Print(expr(ExprKind::Var(name.clone())))), stmt(StmtKind::Break(1, None))]));
+		let looped = stmt(StmtKind::While(None, Box::new(expr(ExprKind::Var(name))), Box::new(body), None));
+		assert_eq!(looped.to_string(), "(while f (block (print f) (break)))");
+	}
+}
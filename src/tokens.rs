@@ -1,11 +1,23 @@
-use super::ErrorReporter;
+use super::Reporter;
 
+use crate::functions::Callable;
+use crate::classes::Instance;
+use crate::symbols::Symbol;
+use crate::symbols::Slot;
+
+use std::cell::Cell;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::rc::Rc;
 use std::string::ToString;
 
 
-lazy_static! {
-  static ref KEYWORD_MAP: HashMap<&'static str, TokenType> = {
+// Literal values are reference counted, so the keyword table can't be shared
+// between threads and is built once per thread instead.
+thread_local! {
+  static KEYWORD_MAP: HashMap<&'static str, TokenType> = {
     let mut m = HashMap::new();
     m.insert("and", TokenType::And);
     m.insert("class", TokenType::Class);
@@ -24,17 +36,28 @@ lazy_static! {
     m.insert("var", TokenType::Var);
     m.insert("while", TokenType::While);
     m.insert("break", TokenType::Break);
+    m.insert("continue", TokenType::Continue);
+    m.insert("global", TokenType::Global);
+    m.insert("switch", TokenType::Switch);
+    m.insert("case", TokenType::Case);
+    m.insert("default", TokenType::Default);
+    m.insert("do", TokenType::Do);
+    m.insert("xor", TokenType::Xor);
+    m.insert("import", TokenType::Import);
     m
   };
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TokenType {
   // Single Character
   LeftParen,
   RightParen,
   LeftBrace,
   RightBrace,
+  LeftBracket,
+  RightBracket,
   Comma,
   Dot,
   Minus,
@@ -44,6 +67,10 @@ pub enum TokenType {
   Star,
   QuestionMark,
   Colon,
+  Ampersand,
+  Pipe,
+  Caret,
+  Tilde,
 
   // One or two character tokens.
   Bang,
@@ -54,6 +81,14 @@ pub enum TokenType {
   GreaterEqual,
   Less,
   LessEqual,
+  LessLess,
+  GreaterGreater,
+  PlusEqual,
+  MinusEqual,
+  PlusPlus,
+  MinusMinus,
+  StarEqual,
+  SlashEqual,
 
   // Literals.
   Identifier,
@@ -74,62 +109,235 @@ pub enum TokenType {
   Var,
   While,
   Break,
+  Continue,
+  Global,
+  Switch,
+  Case,
+  Default,
+  Do,
+  Xor,
+  Import,
+
+  // Comments, only produced by a scanner that keeps trivia. They carry the
+  // text between the delimiters.
+  LineComment(String),
+  BlockComment(String),
 
   Eof,
 }
 
+// Serialized untagged so values map directly onto their JSON counterparts.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(untagged))]
 pub enum Literal {
   Number(f64),
   String(String),
   Nil,
-  Boolean(bool)
+  Boolean(bool),
+  Array(Rc<RefCell<Vec<Literal>>>),
+  Map(Rc<RefCell<HashMap<MapKey, Literal>>>),
+  #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_callable"))]
+  Callable(Rc<Callable>),
+  #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_instance"))]
+  Instance(Rc<RefCell<Instance>>),
 }
 
+#[cfg(feature = "serde")]
+fn serialize_callable<S: serde::Serializer>(func: &Rc<Callable>, s: S) -> Result<S::Ok, S::Error> {
+  s.serialize_str(&func.to_string())
+}
 
-impl std::cmp::Eq for Literal {
-
+#[cfg(feature = "serde")]
+fn serialize_instance<S: serde::Serializer>(instance: &Rc<RefCell<Instance>>, s: S) -> Result<S::Ok, S::Error> {
+  s.serialize_str(&Literal::Instance(instance.clone()).to_string())
 }
 
+
+// Lox equality: numbers, strings and booleans compare by value, with NaN
+// unequal to everything, while arrays, maps, functions and instances are only
+// equal to themselves.
 impl std::cmp::PartialEq for Literal {
   fn eq(&self, other: &Literal) -> bool {
-    std::mem::discriminant(self) == std::mem::discriminant(other)
+    match (self, other) {
+      (Literal::Number(f), Literal::Number(s)) => f == s,
+      (Literal::String(f), Literal::String(s)) => f == s,
+      (Literal::Boolean(f), Literal::Boolean(s)) => f == s,
+      (Literal::Nil, Literal::Nil) => true,
+      (Literal::Array(f), Literal::Array(s)) => Rc::ptr_eq(f, s),
+      (Literal::Map(f), Literal::Map(s)) => Rc::ptr_eq(f, s),
+      (Literal::Callable(f), Literal::Callable(s)) => Rc::ptr_eq(f, s),
+      (Literal::Instance(f), Literal::Instance(s)) => Rc::ptr_eq(f, s),
+      _ => false
+    }
+  }
+}
+
+// Maps are keyed by strings or numbers, which are kept apart, so `m[1]` and
+// `m["1"]` are different entries. Keys that are equal as values are the same
+// key, so `0` and `-0` share one, and NaN, equal to nothing, can't be a key.
+#[derive(Debug, Clone)]
+pub enum MapKey {
+  Number(f64),
+  String(String),
+}
+
+impl MapKey {
+  pub fn from_literal(lit: &Literal) -> Option<MapKey> {
+    match lit {
+      Literal::Number(x) if x.is_nan() => None,
+      Literal::Number(x) => Some(MapKey::Number(if *x == 0.0 { 0.0 } else { *x })),
+      Literal::String(s) => Some(MapKey::String(s.clone())),
+      _ => None
+    }
+  }
+}
+
+impl From<MapKey> for Literal {
+  fn from(key: MapKey) -> Literal {
+    match key {
+      MapKey::Number(x) => Literal::Number(x),
+      MapKey::String(s) => Literal::String(s),
+    }
+  }
+}
+
+impl std::cmp::PartialEq for MapKey {
+  fn eq(&self, other: &MapKey) -> bool {
+    self.cmp(other) == std::cmp::Ordering::Equal
+  }
+}
+
+impl std::cmp::Eq for MapKey {
+
+}
+
+// Numbers sort before strings.
+impl std::cmp::Ord for MapKey {
+  fn cmp(&self, other: &MapKey) -> std::cmp::Ordering {
+    match (self, other) {
+      (MapKey::Number(f), MapKey::Number(s)) => f.total_cmp(s),
+      (MapKey::String(f), MapKey::String(s)) => f.cmp(s),
+      (MapKey::Number(_), MapKey::String(_)) => std::cmp::Ordering::Less,
+      (MapKey::String(_), MapKey::Number(_)) => std::cmp::Ordering::Greater,
+    }
+  }
+}
+
+impl std::cmp::PartialOrd for MapKey {
+  fn partial_cmp(&self, other: &MapKey) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
   }
 }
 
-impl ToString for Literal {
-    fn to_string(&self) -> String {
+impl std::hash::Hash for MapKey {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    match self {
+      MapKey::Number(x) => x.to_bits().hash(state),
+      MapKey::String(s) => s.hash(state),
+    }
+  }
+}
+
+impl std::fmt::Display for MapKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      MapKey::Number(x) => write!(f, "{}", format_number(*x)),
+      MapKey::String(s) => write!(f, "{}", s),
+    }
+  }
+}
+
+// Keys become JSON object keys, which are always strings.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MapKey {
+  fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(self)
+  }
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
       match self {
-        Literal::Number(val) => val.to_string(),
-        Literal::String(s) => s.clone(),
-        Literal::Boolean(t) => t.to_string(),
-        Literal::Nil => "nil".to_owned(),
+        Literal::Number(val) => write!(f, "{}", format_number(*val)),
+        Literal::String(s) => write!(f, "{}", s),
+        Literal::Boolean(t) => write!(f, "{}", t),
+        Literal::Nil => write!(f, "nil"),
+        Literal::Array(items) => {
+          let items: Vec<String> = items.borrow().iter().map(|x| x.to_string()).collect();
+          write!(f, "[{}]", items.join(", "))
+        }
+        Literal::Map(entries) => {
+          let entries = entries.borrow();
+          let mut keys: Vec<&MapKey> = entries.keys().collect();
+          keys.sort();
+          let entries: Vec<String> = keys.iter().map(|k| format!("{}: {}", k, entries[*k])).collect();
+          write!(f, "{{{}}}", entries.join(", "))
+        }
+        Literal::Callable(func) => write!(f, "{}", func.to_string()),
+        Literal::Instance(instance) => write!(f, "<{} instance>", instance.borrow().class_name()),
       }
     }
 }
 
-impl ToString for Token {
-  fn to_string(&self) -> String {
-    self.lexeme.clone()
+// Whole numbers print without a decimal point, others with as many digits as
+// it takes to read them back exactly. Very large values switch to exponent
+// form rather than spelling out every digit.
+fn format_number(val: f64) -> String {
+  if val.is_finite() && val.abs() >= 1e21 {
+    format!("{:e}", val)
+  } else {
+    val.to_string()
+  }
+}
+
+impl std::fmt::Display for Token {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.lexeme)
   }
 }
 
 #[derive(Debug,Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
   t_type: TokenType,
   line: usize,
-  lexeme: String,
+  column: usize,
+  // Byte offset of the token in the source it was scanned from.
+  offset: usize,
+  // Shared so that the parser and interpreter can clone tokens cheaply.
+  lexeme: Rc<str>,
+  // Interned up front for names, which the interpreter looks up repeatedly.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  symbol: Option<Symbol>,
+  // Filled in by the context check for names it resolves, so the
+  // interpreter can find the binding without searching scope by scope.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  slot: Cell<Option<Slot>>,
 }
 
 impl Token {
-  pub fn new(tk: TokenType, lexeme: String, line: usize) -> Token {
+  // Columns count chars from 1 at the start of the token's line.
+  pub fn new(tk: TokenType, lexeme: String, line: usize, column: usize) -> Token {
+    let symbol = match tk {
+      TokenType::Identifier | TokenType::This | TokenType::Super => Some(Symbol::intern(&lexeme)),
+      _ => None
+    };
     Token {
       t_type: tk,
       line,
-      lexeme,
+      column,
+      offset: 0,
+      lexeme: lexeme.into(),
+      symbol,
+      slot: Cell::new(None),
     }
   }
 
+  pub fn with_offset(mut self, offset: usize) -> Token {
+    self.offset = offset;
+    self
+  }
+
   pub fn get_type(&self) -> &TokenType {
     &self.t_type
   }
@@ -138,13 +346,34 @@ impl Token {
     self.line
   }
 
+  pub fn get_column(&self) -> usize {
+    self.column
+  }
+
   pub fn get_lexeme(&self) -> &str {
     &self.lexeme
   }
+
+  pub fn symbol(&self) -> Symbol {
+    self.symbol.unwrap_or_else(|| Symbol::intern(&self.lexeme))
+  }
+
+  pub fn slot(&self) -> Option<Slot> {
+    self.slot.get()
+  }
+
+  pub fn resolve(&self, slot: Slot) {
+    self.slot.set(Some(slot))
+  }
+
+  // The bytes of the source the token was scanned from.
+  pub fn get_span(&self) -> Range<usize> {
+    self.offset..self.offset + self.lexeme.len()
+  }
 }
 
 fn is_alpha(c: char) -> bool {
-  (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+  c.is_ascii_alphabetic() || c == '_'
 }
 
 fn is_alpha_numeric(c: char) -> bool {
@@ -152,49 +381,74 @@ fn is_alpha_numeric(c: char) -> bool {
 }
 
 fn is_digit(c: char) -> bool {
-  c >= '0' && c <= '9'
+  c.is_ascii_digit()
 }
 
 pub struct Scanner<'a> {
   src: String,
-  tokens: Vec<Token>,
+  // The source decoded once, so looking at a char is an index rather than
+  // a UTF-8 decode. Lexemes are still sliced from src by byte offset.
+  chars: Vec<char>,
+  tokens: VecDeque<Token>,
+  // Byte offsets into src.
   start: usize,
   current: usize,
+  // Index into chars of the char at current.
+  position: usize,
   line: usize,
-  err_rep: &'a mut ErrorReporter,
+  // Columns of start and current, counted as the scanner moves so they never
+  // need to look back through the line.
+  start_column: usize,
+  column: usize,
+  trivia: bool,
+  finished: bool,
+  err_rep: &'a mut dyn Reporter,
 }
 
 impl<'a> Scanner<'a> {
-  pub fn new(src: String, err_hand: &'a mut ErrorReporter) -> Scanner {
+  pub fn new(src: String, err_hand: &'a mut dyn Reporter) -> Scanner<'a> {
+    Scanner::build(src, err_hand, false)
+  }
+
+  // Keeps comments as tokens rather than skipping them, for tools like
+  // formatters. The parser doesn't expect them.
+  pub fn new_with_trivia(src: String, err_hand: &'a mut dyn Reporter) -> Scanner<'a> {
+    Scanner::build(src, err_hand, true)
+  }
+
+  fn build(src: String, err_hand: &'a mut dyn Reporter, trivia: bool) -> Scanner<'a> {
     Scanner {
       line: 1,
       current: 0,
       start: 0,
+      position: 0,
+      start_column: 1,
+      column: 1,
+      trivia,
+      chars: src.chars().collect(),
       src,
-      tokens: Vec::new(),
+      tokens: VecDeque::new(),
+      finished: false,
       err_rep: err_hand,
     }
   }
 
-  pub fn scan_tokens(mut self) -> Vec<Token> {
-    while !self.is_at_end() {
-      self.start = self.current;
-      self.grab_token();
-    }
-
-    self
-      .tokens
-      .push(Token::new(TokenType::Eof, "".to_owned(), self.line));
-    self.tokens
+  pub fn scan_tokens(self) -> Vec<Token> {
+    self.collect()
   }
 
   fn is_at_end(&self) -> bool {
-    self.current >= self.src.len()
+    self.position >= self.chars.len()
   }
 
   fn add_token(&mut self, t_type: TokenType) {
+    self.add_token_at(t_type, self.line)
+  }
+
+  // For tokens spanning lines, which belong to the line they start on.
+  fn add_token_at(&mut self, t_type: TokenType, line: usize) {
     let text = self.src[self.start..self.current].to_owned();
-    self.tokens.push(Token::new(t_type, text, self.line))
+    self.tokens.push_back(Token::new(t_type, text, line, self.start_column).with_offset(self.start))
   }
 
   fn grab_token(&mut self) {
@@ -205,14 +459,45 @@ impl<'a> Scanner<'a> {
       ')' => self.add_token(TokenType::RightParen),
       '{' => self.add_token(TokenType::LeftBrace),
       '}' => self.add_token(TokenType::RightBrace),
+      '[' => self.add_token(TokenType::LeftBracket),
+      ']' => self.add_token(TokenType::RightBracket),
       ',' => self.add_token(TokenType::Comma),
       '.' => self.add_token(TokenType::Dot),
-      '-' => self.add_token(TokenType::Minus),
-      '+' => self.add_token(TokenType::Plus),
+      '-' => {
+        let tk = if self.match_char('=') {
+          TokenType::MinusEqual
+        } else if self.match_char('-') {
+          TokenType::MinusMinus
+        } else {
+          TokenType::Minus
+        };
+        self.add_token(tk)
+      }
+      '+' => {
+        let tk = if self.match_char('=') {
+          TokenType::PlusEqual
+        } else if self.match_char('+') {
+          TokenType::PlusPlus
+        } else {
+          TokenType::Plus
+        };
+        self.add_token(tk)
+      }
       ';' => self.add_token(TokenType::Semicolon),
-      '*' => self.add_token(TokenType::Star),
+      '*' => {
+        let tk = if self.match_char('=') {
+          TokenType::StarEqual
+        } else {
+          TokenType::Star
+        };
+        self.add_token(tk)
+      }
       '?' => self.add_token(TokenType::QuestionMark),
       ':' => self.add_token(TokenType::Colon),
+      '&' => self.add_token(TokenType::Ampersand),
+      '|' => self.add_token(TokenType::Pipe),
+      '^' => self.add_token(TokenType::Caret),
+      '~' => self.add_token(TokenType::Tilde),
       '!' => {
         let tk = if self.match_char('=') {
           TokenType::BangEqual
@@ -232,6 +517,8 @@ impl<'a> Scanner<'a> {
       '<' => {
         let tk = if self.match_char('=') {
           TokenType::LessEqual
+        } else if self.match_char('<') {
+          TokenType::LessLess
         } else {
           TokenType::Less
         };
@@ -240,6 +527,8 @@ impl<'a> Scanner<'a> {
       '>' => {
         let tk = if self.match_char('=') {
           TokenType::GreaterEqual
+        } else if self.match_char('>') {
+          TokenType::GreaterGreater
         } else {
           TokenType::Greater
         };
@@ -247,10 +536,16 @@ impl<'a> Scanner<'a> {
       }
       '/' => {
         if self.match_char('/') {
-          while self.get_current_char() != Some('\n') && !self.is_at_end() {
+          while !matches!(self.get_current_char(), Some('\n') | Some('\r') | None) {
             self.advance();
           }
+
+          if self.trivia {
+            let text = self.src[self.start + 2..self.current].to_owned();
+            self.add_token(TokenType::LineComment(text));
+          }
         } else if self.match_char('*') {
+          let line = self.line;
           let mut term = 1;
           while let (Some(curr), Some(next)) = (self.get_current_char(), self.peek_next()) {
             if curr == '*' && next == '/' {
@@ -261,8 +556,6 @@ impl<'a> Scanner<'a> {
               term += 1;
               self.advance();
               self.advance();
-            } else if curr == '\n' {
-              self.line += 1;
             } else {
               self.advance();
             };
@@ -273,21 +566,23 @@ impl<'a> Scanner<'a> {
           }
 
           if term != 0 {
+            while self.advance().is_some() {}
             self.err_rep.error(self.line, "Unclosed block comment.")
+          } else if self.trivia {
+            let text = self.src[self.start + 2..self.current - 2].to_owned();
+            self.add_token_at(TokenType::BlockComment(text), line);
           }
+        } else if self.match_char('=') {
+          self.add_token(TokenType::SlashEqual);
         } else {
           self.add_token(TokenType::Slash);
         }
       }
-      ' ' | '\r' | '\t' => (),
-      '\n' => {
-        self.line += 1;
-        ()
-      }
+      ' ' | '\r' | '\t' | '\n' => (),
       '"' => self.string(),
-      '0'...'9' => self.number(),
+      '0'..='9' => self.number(),
       x if is_alpha(x) => self.identifier(),
-      _ => self.err_rep.error(self.line, "Unexpected character."),
+      _ => self.err_rep.error_at(self.line, self.start_column, "Unexpected character."),
     }
   }
 
@@ -302,8 +597,8 @@ impl<'a> Scanner<'a> {
 
     let name = &self.src[self.start..self.current];
 
-    if let Some(tok) = KEYWORD_MAP.get(name) {
-      self.add_token(tok.clone());
+    if let Some(tok) = KEYWORD_MAP.with(|m| m.get(name).cloned()) {
+      self.add_token(tok);
     } else {
       self.add_token(TokenType::Identifier)
     }
@@ -343,8 +638,6 @@ impl<'a> Scanner<'a> {
       if next_char == '"' {
         terminated = true;
         break;
-      } else if next_char == '\n' {
-        self.line += 1;
       }
     }
 
@@ -371,16 +664,201 @@ impl<'a> Scanner<'a> {
   }
 
   fn get_current_char(&self) -> Option<char> {
-    self.src[(self.current)..].chars().next()
+    self.chars.get(self.position).copied()
   }
 
   fn peek_next(&self) -> Option<char> {
-    self.src[self.current + 1..].chars().next()
+    self.chars.get(self.position + 1).copied()
   }
 
+  // Lines end at "\n", "\r\n" or a lone "\r", so a source counts the same
+  // lines whichever style it was saved with.
   fn advance(&mut self) -> Option<char> {
     let c = self.get_current_char();
-    self.current += 1;
+    if let Some(c) = c {
+      self.current += c.len_utf8();
+      self.position += 1;
+    }
+
+    if c == Some('\n') || (c == Some('\r') && self.get_current_char() != Some('\n')) {
+      self.line += 1;
+      self.column = 1;
+    } else {
+      self.column += 1;
+    }
     c
   }
 }
+
+// Whitespace and comments produce no tokens, so a single step of the scanner
+// may come back empty; keep stepping until there is a token or input runs out.
+impl Iterator for Scanner<'_> {
+  type Item = Token;
+
+  fn next(&mut self) -> Option<Token> {
+    while self.tokens.is_empty() && !self.finished {
+      if self.is_at_end() {
+        self.tokens.push_back(Token::new(TokenType::Eof, "".to_owned(), self.line, self.column).with_offset(self.src.len()));
+        self.finished = true;
+      } else {
+        self.start = self.current;
+        self.start_column = self.column;
+        self.grab_token();
+      }
+    }
+
+    self.tokens.pop_front()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::ErrorReporter;
+
+  #[test]
+  fn nested_block_comment_line_count() {
+    let mut err_rep = ErrorReporter::new();
+    let src = "/* one\n /* two\n */ three\n */\nvar a = 1; @";
+    let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+
+    assert_eq!(tokens[0].get_line(), 5);
+    assert_eq!(err_rep.diagnostics().len(), 1);
+    assert_eq!(err_rep.diagnostics()[0].line, 5);
+    assert_eq!(err_rep.diagnostics()[0].message, "Unexpected character.");
+  }
+
+  #[test]
+  fn unclosed_block_comment() {
+    let mut err_rep = ErrorReporter::new();
+    let tokens = Scanner::new("/* one\n /* two */\n*".to_owned(), &mut err_rep).scan_tokens();
+
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(err_rep.diagnostics()[0].line, 3);
+    assert_eq!(err_rep.diagnostics()[0].message, "Unclosed block comment.");
+  }
+
+  #[test]
+  fn iterates_tokens_lazily() {
+    let src = "var a = 1; // note\nprint a + \"b\";";
+    let mut err_rep = ErrorReporter::new();
+    let all = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+
+    let mut err_rep = ErrorReporter::new();
+    let mut scanner = Scanner::new(src.to_owned(), &mut err_rep);
+    for expected in all.iter() {
+      let token = scanner.next().unwrap();
+      assert_eq!(token.get_type(), expected.get_type());
+      assert_eq!(token.get_lexeme(), expected.get_lexeme());
+      assert_eq!((token.get_line(), token.get_column()), (expected.get_line(), expected.get_column()));
+    }
+    assert!(scanner.next().is_none());
+    assert!(matches!(all.last().unwrap().get_type(), TokenType::Eof));
+  }
+  #[test]
+  fn number_formatting() {
+    assert_eq!(Literal::Number(1.0).to_string(), "1");
+    assert_eq!(Literal::Number(-4.0).to_string(), "-4");
+    assert_eq!(Literal::Number(1.5).to_string(), "1.5");
+    assert_eq!(Literal::Number(10.0 / 3.0).to_string(), "3.3333333333333335");
+    assert_eq!(Literal::Number(123456789012.0).to_string(), "123456789012");
+    assert_eq!(Literal::Number(1e300).to_string(), "1e300");
+    assert_eq!(Literal::Number(-2.5e21).to_string(), "-2.5e21");
+  }
+
+  #[test]
+  fn columns_on_long_lines() {
+    // Long enough that finding each column by rescanning the line would
+    // make this test crawl.
+    let src = format!("\"é\"\n{}x", "a + ".repeat(100_000));
+    let mut err_rep = ErrorReporter::new();
+    let tokens = Scanner::new(src, &mut err_rep).scan_tokens();
+
+    assert_eq!(tokens.len(), 200_003);
+    assert_eq!((tokens[1].get_line(), tokens[1].get_column()), (2, 1));
+    assert_eq!(tokens[2].get_column(), 3);
+    let x = &tokens[tokens.len() - 2];
+    assert_eq!((x.get_lexeme(), x.get_column()), ("x", 400_001));
+    assert_eq!(tokens.last().unwrap().get_column(), 400_002);
+  }
+
+  #[test]
+  fn trivia_mode_keeps_comments() {
+    let src = "// one\nvar a; /* two\n /* three */ */ a // four";
+    let mut err_rep = ErrorReporter::new();
+    let tokens = Scanner::new_with_trivia(src.to_owned(), &mut err_rep).scan_tokens();
+    let comments: Vec<(TokenType, usize)> = tokens.iter()
+      .filter(|tk| matches!(tk.get_type(), TokenType::LineComment(_) | TokenType::BlockComment(_)))
+      .map(|tk| (tk.get_type().clone(), tk.get_line()))
+      .collect();
+
+    assert_eq!(comments, vec![
+      (TokenType::LineComment(" one".to_owned()), 1),
+      (TokenType::BlockComment(" two\n /* three */ ".to_owned()), 2),
+      (TokenType::LineComment(" four".to_owned()), 3),
+    ]);
+    assert_eq!(tokens.len(), 8);
+
+    let mut err_rep = ErrorReporter::new();
+    assert_eq!(Scanner::new(src.to_owned(), &mut err_rep).scan_tokens().len(), 5);
+  }
+
+  #[test]
+  fn shifts_scan_apart_from_comparisons() {
+    let mut err_rep = ErrorReporter::new();
+    let types: Vec<TokenType> = Scanner::new("a << b < c <= d >> e > f >= g".to_owned(), &mut err_rep)
+      .filter(|tk| !matches!(tk.get_type(), TokenType::Identifier | TokenType::Eof))
+      .map(|tk| tk.get_type().clone())
+      .collect();
+
+    assert_eq!(types, vec![TokenType::LessLess, TokenType::Less, TokenType::LessEqual,
+      TokenType::GreaterGreater, TokenType::Greater, TokenType::GreaterEqual]);
+  }
+
+  #[test]
+  fn spans_index_the_source() {
+    let src = "var a = \"é\nb\";\n  a += 1;";
+    let mut err_rep = ErrorReporter::new();
+    let tokens = Scanner::new(src.to_owned(), &mut err_rep).scan_tokens();
+
+    for tk in tokens.iter() {
+      assert_eq!(&src[tk.get_span()], tk.get_lexeme());
+    }
+    assert!(!err_rep.had_error);
+    assert_eq!(tokens[3].get_span(), 8..14);
+    assert_eq!(tokens.last().unwrap().get_span(), src.len()..src.len());
+  }
+
+  #[test]
+  fn every_line_ending_counts_once() {
+    let src = "var a = 1; // one\n/* two\nthree */ print \"four\nfive\";\n\n  a;\n";
+    let places = |src: String| {
+      let mut err_rep = ErrorReporter::new();
+      let tokens = Scanner::new(src, &mut err_rep).scan_tokens();
+      assert!(!err_rep.had_error);
+      tokens.iter().map(|tk| (tk.get_line(), tk.get_column())).collect::<Vec<_>>()
+    };
+
+    let unix = places(src.to_owned());
+    assert_eq!(unix.last(), Some(&(7, 1)));
+    assert_eq!(unix, places(src.replace('\n', "\r\n")));
+    assert_eq!(unix, places(src.replace('\n', "\r")));
+  }
+
+  // Scanning is linear, so a few megabytes take well under a second even
+  // unoptimized. Going quadratic again would take hours.
+  #[test]
+  fn scans_large_input_in_linear_time() {
+    let line = "var cafe = \"ünïcode\" + 12.5; // a comment\n";
+    let src = line.repeat(50_000);
+    let started = std::time::Instant::now();
+    let mut err_rep = ErrorReporter::new();
+    let tokens = Scanner::new(src.clone(), &mut err_rep).scan_tokens();
+
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(err_rep.diagnostics().is_empty());
+    assert_eq!(tokens.len(), 7 * 50_000 + 1);
+    assert_eq!(tokens[tokens.len() - 2].get_line(), 50_000);
+    assert_eq!(tokens.last().unwrap().get_span(), src.len()..src.len());
+  }
+}